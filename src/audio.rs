@@ -0,0 +1,373 @@
+use std::fmt;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Arc;
+use std::time::Duration;
+
+use rodio::source::UniformSourceIterator;
+use rodio::{Decoder, OutputStream, Sink, Source};
+
+use crate::chart::NoteType;
+use crate::hitsound::{HitSounds, ScheduledHit};
+
+/// Song file extensions probed next to the chart, in order of preference
+pub const SONG_EXTENSIONS: [&str; 3] = ["ogg", "wav", "mp3"];
+
+/// Drift (in seconds) between the frame clock and the audio clock before snapping
+pub const MAX_CLOCK_DRIFT: f64 = 0.05;
+
+/// Fraction of the drift corrected each frame when below `MAX_CLOCK_DRIFT`
+pub const CLOCK_CORRECTION: f64 = 0.1;
+
+/// Output format used when there is no song to play
+pub const SILENT_CHANNELS: u16 = 2;
+pub const SILENT_SAMPLE_RATE: u32 = 44100;
+
+/// Sentinel meaning "no seek pending"
+const NO_SEEK: u64 = u64::MAX;
+
+/// Errors raised while loading or playing the song
+#[derive(Debug)]
+pub enum AudioError {
+    Io(std::io::Error),
+    Decode(rodio::decoder::DecoderError),
+    Stream(rodio::StreamError),
+    Play(rodio::PlayError),
+    Encode(hound::Error),
+}
+
+impl fmt::Display for AudioError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AudioError::Io(e) => write!(f, "cannot open audio file: {}", e),
+            AudioError::Decode(e) => write!(f, "cannot decode audio file: {}", e),
+            AudioError::Stream(e) => write!(f, "cannot open audio output: {}", e),
+            AudioError::Play(e) => write!(f, "cannot start playback: {}", e),
+            AudioError::Encode(e) => write!(f, "cannot write WAV file: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for AudioError {}
+
+/// Fully decoded audio, interleaved `f32` samples
+#[derive(Clone)]
+pub struct AudioBuffer {
+    pub samples: Arc<[f32]>,
+    pub channels: u16,
+    pub sample_rate: u32,
+}
+
+impl AudioBuffer {
+    /// Decode an OGG/WAV/MP3/FLAC file into memory
+    pub fn decode(path: &Path) -> Result<Self, AudioError> {
+        let file = File::open(path).map_err(AudioError::Io)?;
+        let decoder = Decoder::new(BufReader::new(file)).map_err(AudioError::Decode)?;
+        let channels = decoder.channels();
+        let sample_rate = decoder.sample_rate();
+        let samples: Vec<f32> = decoder.convert_samples().collect();
+
+        Ok(Self {
+            samples: samples.into(),
+            channels,
+            sample_rate,
+        })
+    }
+
+    /// Decode a file and convert it to the given channel count and sample rate
+    pub fn decode_as(path: &Path, channels: u16, sample_rate: u32) -> Result<Self, AudioError> {
+        let file = File::open(path).map_err(AudioError::Io)?;
+        let decoder = Decoder::new(BufReader::new(file)).map_err(AudioError::Decode)?;
+        let samples: Vec<f32> =
+            UniformSourceIterator::<_, f32>::new(decoder, channels, sample_rate).collect();
+
+        Ok(Self {
+            samples: samples.into(),
+            channels,
+            sample_rate,
+        })
+    }
+
+    /// Silent buffer, used when no song is available so hit sounds still play
+    pub fn silence(channels: u16, sample_rate: u32) -> Self {
+        Self {
+            samples: Vec::new().into(),
+            channels,
+            sample_rate,
+        }
+    }
+
+    /// Number of sample frames (one sample per channel)
+    pub fn frames(&self) -> u64 {
+        self.samples.len() as u64 / self.channels.max(1) as u64
+    }
+
+    /// Length in seconds
+    pub fn duration(&self) -> f64 {
+        self.frames() as f64 / self.sample_rate as f64
+    }
+}
+
+/// Find a song next to the chart: same file stem with one of `SONG_EXTENSIONS`
+pub fn find_song_for_chart(chart_path: &Path) -> Option<PathBuf> {
+    SONG_EXTENSIONS
+        .iter()
+        .map(|ext| chart_path.with_extension(ext))
+        .find(|path| path.is_file())
+}
+
+/// State shared between the audio thread and the game
+struct MixerState {
+    /// Frame currently being output
+    frame: AtomicU64,
+    /// Frame requested by the game, or `NO_SEEK`
+    seek: AtomicU64,
+    /// Music volume (`f32` bits)
+    music_volume: AtomicU32,
+    /// Hit sound volume (`f32` bits)
+    effect_volume: AtomicU32,
+}
+
+/// Hit sound currently playing
+struct Voice {
+    samples: Arc<[f32]>,
+    frame: usize,
+}
+
+/// Endless source mixing the song buffer with hit sounds at the shared playhead.
+/// Emits silence past the end so the clock keeps running for trailing notes.
+struct MixerSource {
+    song: AudioBuffer,
+    effects: HitSounds,
+    hits: Vec<ScheduledHit>,
+    /// Hit sounds triggered live by the game
+    triggers: Receiver<NoteType>,
+    state: Arc<MixerState>,
+    frame: u64,
+    channel: u16,
+    /// Index of the next hit to trigger
+    next_hit: usize,
+    voices: Vec<Voice>,
+    music_volume: f32,
+    effect_volume: f32,
+}
+
+impl MixerSource {
+    /// Called at the start of every frame: applies seeks and triggers hits
+    fn begin_frame(&mut self) {
+        let seek = self.state.seek.load(Ordering::Acquire);
+        if seek != NO_SEEK {
+            // Hits before the new position are skipped rather than replayed,
+            // so a seek never fires a hit twice
+            self.frame = seek;
+            self.next_hit = self.hits.partition_point(|hit| hit.frame < seek);
+            self.voices.clear();
+            self.state.frame.store(self.frame, Ordering::Release);
+            let _ = self.state.seek.compare_exchange(
+                seek,
+                NO_SEEK,
+                Ordering::AcqRel,
+                Ordering::Relaxed,
+            );
+        } else {
+            self.state.frame.store(self.frame, Ordering::Release);
+        }
+
+        self.music_volume = f32::from_bits(self.state.music_volume.load(Ordering::Relaxed));
+        self.effect_volume = f32::from_bits(self.state.effect_volume.load(Ordering::Relaxed));
+
+        while let Some(hit) = self.hits.get(self.next_hit) {
+            if hit.frame > self.frame {
+                break;
+            }
+            self.start_voice(hit.note_type);
+            self.next_hit += 1;
+        }
+        while let Ok(note_type) = self.triggers.try_recv() {
+            self.start_voice(note_type);
+        }
+    }
+
+    fn start_voice(&mut self, note_type: NoteType) {
+        let samples = self.effects.sample_for(note_type);
+        if !samples.is_empty() {
+            self.voices.push(Voice {
+                samples: samples.clone(),
+                frame: 0,
+            });
+        }
+    }
+
+    /// Called at the end of every frame: advances and retires hit sounds
+    fn end_frame(&mut self) {
+        let channels = self.song.channels as usize;
+        for voice in &mut self.voices {
+            voice.frame += 1;
+        }
+        self.voices
+            .retain(|voice| voice.frame * channels < voice.samples.len());
+        self.frame += 1;
+    }
+}
+
+impl Iterator for MixerSource {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.channel == 0 {
+            self.begin_frame();
+        }
+
+        let channels = self.song.channels as usize;
+        let channel = self.channel as usize;
+        let index = self.frame as usize * channels + channel;
+        let mut sample = self.song.samples.get(index).copied().unwrap_or(0.0) * self.music_volume;
+        for voice in &self.voices {
+            let effect = voice
+                .samples
+                .get(voice.frame * channels + channel)
+                .copied()
+                .unwrap_or(0.0);
+            sample += effect * self.effect_volume;
+        }
+
+        self.channel += 1;
+        if self.channel >= self.song.channels {
+            self.channel = 0;
+            self.end_frame();
+        }
+
+        Some(sample)
+    }
+}
+
+impl Source for MixerSource {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        self.song.channels
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.song.sample_rate
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
+
+/// Song and hit sound playback; its playhead is the master clock of the game.
+/// Not `Send` (holds the output stream), so it lives in a non-send resource.
+pub struct SongPlayer {
+    _stream: OutputStream,
+    sink: Sink,
+    state: Arc<MixerState>,
+    triggers: Sender<NoteType>,
+    sample_rate: u32,
+    duration: f64,
+}
+
+impl SongPlayer {
+    /// Open the default output device and start mixing, paused at 0.
+    /// `hits` are played at their frames; more can be triggered with `play_hit`.
+    pub fn new(
+        song: AudioBuffer,
+        effects: HitSounds,
+        hits: Vec<ScheduledHit>,
+    ) -> Result<Self, AudioError> {
+        let (stream, handle) = OutputStream::try_default().map_err(AudioError::Stream)?;
+        let sink = Sink::try_new(&handle).map_err(AudioError::Play)?;
+        sink.pause();
+
+        let state = Arc::new(MixerState {
+            frame: AtomicU64::new(0),
+            seek: AtomicU64::new(NO_SEEK),
+            music_volume: AtomicU32::new(1.0f32.to_bits()),
+            effect_volume: AtomicU32::new(1.0f32.to_bits()),
+        });
+        let sample_rate = song.sample_rate;
+        let duration = song.duration();
+        let (triggers, trigger_receiver) = mpsc::channel();
+
+        sink.append(MixerSource {
+            song,
+            effects,
+            hits,
+            triggers: trigger_receiver,
+            state: state.clone(),
+            frame: 0,
+            channel: 0,
+            next_hit: 0,
+            voices: Vec::new(),
+            music_volume: 1.0,
+            effect_volume: 1.0,
+        });
+
+        Ok(Self {
+            _stream: stream,
+            sink,
+            state,
+            triggers,
+            sample_rate,
+            duration,
+        })
+    }
+
+    /// Length of the song in seconds, 0 when playing over silence
+    pub fn duration(&self) -> f64 {
+        self.duration
+    }
+
+    /// Current song position in seconds (pending seeks included)
+    pub fn position(&self) -> f64 {
+        let seek = self.state.seek.load(Ordering::Acquire);
+        let frame = if seek != NO_SEEK {
+            seek
+        } else {
+            self.state.frame.load(Ordering::Acquire)
+        };
+        frame as f64 / self.sample_rate as f64
+    }
+
+    /// Move the playhead to `seconds`
+    pub fn seek(&self, seconds: f64) {
+        let frame = (seconds.max(0.0) * self.sample_rate as f64).round() as u64;
+        self.state.seek.store(frame, Ordering::Release);
+    }
+
+    /// Play the hit sound of `note_type` now, e.g. when the player hits a note
+    pub fn play_hit(&self, note_type: NoteType) {
+        let _ = self.triggers.send(note_type);
+    }
+
+    pub fn set_playing(&self, playing: bool) {
+        if playing {
+            self.sink.play();
+        } else {
+            self.sink.pause();
+        }
+    }
+
+    /// Playback rate; the output is resampled (pitch follows the rate)
+    pub fn set_rate(&self, rate: f64) {
+        self.sink.set_speed(rate as f32);
+    }
+
+    pub fn set_music_volume(&self, volume: f32) {
+        self.state
+            .music_volume
+            .store(volume.to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn set_effect_volume(&self, volume: f32) {
+        self.state
+            .effect_volume
+            .store(volume.to_bits(), Ordering::Relaxed);
+    }
+}
@@ -1,6 +1,89 @@
+use std::fmt;
+use std::io;
+use std::path::Path;
+
 use serde::{Deserialize, Serialize};
+use serde_json::ser::PrettyFormatter;
+use serde_json::{Map, Serializer, Value};
+
+use crate::easing::{keyframe_ease, CustomEase};
+use crate::format::{self, JsonShape, SUPPORTED_FILE_VERSIONS};
+use crate::validate::{validate, Diagnostic, Severity};
+
+/// Most validation errors listed by `ChartLoadError`'s message; `check` lists them all
+const LISTED_ERRORS: usize = 20;
+
+/// Errors raised while loading a chart
+#[derive(Debug)]
+pub enum ChartLoadError {
+    Io(std::io::Error),
+    /// Malformed JSON, or JSON not shaped like a chart. `line` is 0 when the
+    /// error has no position, as for a field missing from the chart.
+    Json {
+        line: usize,
+        column: usize,
+        message: String,
+    },
+    UnsupportedVersion(u32),
+    /// Errors reported by `validate`
+    Invalid(Vec<Diagnostic>),
+}
+
+impl From<serde_json::Error> for ChartLoadError {
+    fn from(e: serde_json::Error) -> Self {
+        let (line, column) = (e.line(), e.column());
+        let mut message = e.to_string();
+        // serde_json appends the position, which is reported separately
+        let position = format!(" at line {} column {}", line, column);
+        if message.ends_with(&position) {
+            message.truncate(message.len() - position.len());
+        }
+        ChartLoadError::Json {
+            line,
+            column,
+            message,
+        }
+    }
+}
+
+impl fmt::Display for ChartLoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChartLoadError::Io(e) => write!(f, "cannot read chart file: {}", e),
+            ChartLoadError::Json {
+                line,
+                column,
+                message,
+            } if *line > 0 => {
+                write!(
+                    f,
+                    "invalid chart JSON at line {}, column {}: {}",
+                    line, column, message
+                )
+            }
+            ChartLoadError::Json { message, .. } => write!(f, "invalid chart JSON: {}", message),
+            ChartLoadError::UnsupportedVersion(version) => write!(
+                f,
+                "unsupported fileVersion {}, supported versions are {:?}",
+                version, SUPPORTED_FILE_VERSIONS
+            ),
+            ChartLoadError::Invalid(diagnostics) => {
+                write!(f, "chart has {} errors", diagnostics.len())?;
+                for diagnostic in diagnostics.iter().take(LISTED_ERRORS) {
+                    write!(f, "\n  {}", diagnostic)?;
+                }
+                if diagnostics.len() > LISTED_ERRORS {
+                    write!(f, "\n  ... and {} more", diagnostics.len() - LISTED_ERRORS)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for ChartLoadError {}
 
-/// RGBA color representation
+/// RGBA color representation; stored in charts as a `StoredColor`
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, Default)]
 pub struct Color {
     pub r: u8,
@@ -10,7 +93,8 @@ pub struct Color {
 }
 
 impl Color {
-    pub fn to_bevy_color(&self) -> bevy::prelude::Color {
+    #[cfg(feature = "player")]
+    pub fn to_bevy_color(self) -> bevy::prelude::Color {
         bevy::prelude::Color::srgba(
             self.r as f32 / 255.0,
             self.g as f32 / 255.0,
@@ -50,11 +134,25 @@ impl Color {
     }
 }
 
+/// Color as stored in a chart. The color itself stays `Copy` for rendering;
+/// the fields around it are kept here.
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct StoredColor {
+    #[serde(flatten)]
+    pub color: Color,
+    /// Fields this player does not read, written back unchanged
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
 /// Theme with color list
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Theme {
     #[serde(rename = "colorsList")]
-    pub colors_list: Vec<Color>,
+    pub colors_list: Vec<StoredColor>,
+    /// Fields this player does not read, written back unchanged
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
 }
 
 /// Challenge time period
@@ -66,6 +164,9 @@ pub struct ChallengeTime {
     pub end: f64,
     #[serde(rename = "transTime")]
     pub trans_time: f64,
+    /// Fields this player does not read, written back unchanged
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
 }
 
 /// BPM shift point
@@ -77,6 +178,9 @@ pub struct BpmShift {
     pub ease_type: u8,
     #[serde(rename = "floorPosition")]
     pub floor_position: f64,
+    /// Fields this player does not read, written back unchanged
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
 }
 
 /// Line point (keyframe)
@@ -85,25 +189,39 @@ pub struct LinePoint {
     pub time: f64,
     #[serde(rename = "xPosition")]
     pub x_position: f64,
-    pub color: Color,
+    pub color: StoredColor,
     #[serde(rename = "easeType")]
     pub ease_type: u8,
     #[serde(rename = "canvasIndex")]
     pub canvas_index: usize,
     #[serde(rename = "floorPosition")]
     pub floor_position: f64,
-    /// Cached floor position value (computed at runtime)
-    #[serde(skip)]
-    pub fp: Option<f64>,
-    /// Cached mixed color (computed at runtime)
-    #[serde(skip)]
-    #[allow(dead_code)]
-    pub mix_color: Option<Color>,
+    /// Curve used instead of `ease_type` when set
+    #[serde(
+        rename = "customEase",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub custom_ease: Option<CustomEase>,
+    /// Fields this player does not read, written back unchanged
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+impl LinePoint {
+    /// Progress along the segment to the next point at `t`
+    pub fn ease(&self, t: f32) -> f32 {
+        keyframe_ease(self.ease_type, self.custom_ease.as_ref(), t)
+    }
+
+    /// Whether the segment to the next point is straight
+    pub fn is_linear(&self) -> bool {
+        self.custom_ease.is_none() && self.ease_type == 0
+    }
 }
 
 /// Note types
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
-#[allow(dead_code)]
 pub enum NoteType {
     Tap = 0,
     Drag = 1,
@@ -129,28 +247,37 @@ pub struct Note {
     pub time: f64,
     #[serde(rename = "floorPosition")]
     pub floor_position: f64,
-    #[serde(rename = "otherInformations", default)]
+    #[serde(rename = "otherInformations")]
     pub other_informations: Vec<f64>,
+    /// Fields this player does not read, written back unchanged
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
 }
 
 /// Color transition event for judge ring
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct JudgeRingColor {
     #[serde(rename = "startColor")]
-    pub start_color: Color,
+    pub start_color: StoredColor,
     #[serde(rename = "endColor")]
-    pub end_color: Color,
+    pub end_color: StoredColor,
     pub time: f64,
+    /// Fields this player does not read, written back unchanged
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
 }
 
 /// Color transition event for line
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LineColor {
     #[serde(rename = "startColor")]
-    pub start_color: Color,
+    pub start_color: StoredColor,
     #[serde(rename = "endColor")]
-    pub end_color: Color,
+    pub end_color: StoredColor,
     pub time: f64,
+    /// Fields this player does not read, written back unchanged
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
 }
 
 /// Line containing points and notes
@@ -161,8 +288,11 @@ pub struct Line {
     pub notes: Vec<Note>,
     #[serde(rename = "judgeRingColor")]
     pub judge_ring_color: Vec<JudgeRingColor>,
-    #[serde(rename = "lineColor", default)]
+    #[serde(rename = "lineColor")]
     pub line_color: Vec<LineColor>,
+    /// Fields this player does not read, written back unchanged
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
 }
 
 /// Keyframe for canvas movement
@@ -174,9 +304,23 @@ pub struct KeyPoint {
     pub ease_type: u8,
     #[serde(rename = "floorPosition")]
     pub floor_position: f64,
-    /// Cached floor position (computed at runtime)
-    #[serde(skip)]
-    pub fp: Option<f64>,
+    /// Curve used instead of `ease_type` when set
+    #[serde(
+        rename = "customEase",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub custom_ease: Option<CustomEase>,
+    /// Fields this player does not read, written back unchanged
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+impl KeyPoint {
+    /// Progress toward the next keypoint's value at `t`
+    pub fn ease(&self, t: f32) -> f32 {
+        keyframe_ease(self.ease_type, self.custom_ease.as_ref(), t)
+    }
 }
 
 /// Canvas movement configuration
@@ -187,23 +331,29 @@ pub struct CanvasMove {
     pub x_position_key_points: Vec<KeyPoint>,
     #[serde(rename = "speedKeyPoints")]
     pub speed_key_points: Vec<KeyPoint>,
+    /// Fields this player does not read, written back unchanged
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
 }
 
 /// Camera movement configuration
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct CameraMove {
-    #[serde(rename = "scaleKeyPoints")]
+    #[serde(rename = "scaleKeyPoints", default)]
     pub scale_key_points: Vec<KeyPoint>,
-    #[serde(rename = "xPositionKeyPoints")]
+    #[serde(rename = "xPositionKeyPoints", default)]
     pub x_position_key_points: Vec<KeyPoint>,
+    /// Fields this player does not read, written back unchanged
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
 }
 
-/// Main chart data structure
+/// Main chart data structure; chart files of every version are migrated to it by `format`
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Chart {
     #[serde(rename = "fileVersion")]
     pub file_version: u32,
-    #[serde(rename = "songsName", default)]
+    #[serde(rename = "songsName")]
     pub songs_name: String,
     pub themes: Vec<Theme>,
     #[serde(rename = "challengeTimes")]
@@ -218,10 +368,152 @@ pub struct Chart {
     pub canvas_moves: Vec<CanvasMove>,
     #[serde(rename = "cameraMove")]
     pub camera_move: CameraMove,
+    /// Fields this player does not read, written back unchanged
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+    /// Layout of the file the chart was read from, restored when it is written
+    #[serde(skip)]
+    pub shape: JsonShape,
 }
 
 impl Chart {
-    pub fn from_json(json: &str) -> Result<Self, serde_json::Error> {
-        serde_json::from_str(json)
+    /// Parse and migrate a chart of any supported `fileVersion`, without validating it
+    pub fn from_json(json: &str) -> Result<Self, ChartLoadError> {
+        format::parse(json)
+    }
+
+    /// Read, parse and validate a chart file; charts with validation errors are rejected,
+    /// while warnings, which the renderer works around, are not
+    pub fn load(path: &Path) -> Result<Self, ChartLoadError> {
+        let json = std::fs::read_to_string(path).map_err(ChartLoadError::Io)?;
+        let chart = Self::from_json(&json)?;
+        let errors: Vec<Diagnostic> = validate(&chart)
+            .into_iter()
+            .filter(|diagnostic| diagnostic.severity == Severity::Error)
+            .collect();
+        if errors.is_empty() {
+            Ok(chart)
+        } else {
+            Err(ChartLoadError::Invalid(errors))
+        }
+    }
+
+    /// Compact JSON in the current file layout
+    pub fn to_json(&self) -> Result<String, serde_json::Error> {
+        serde_json::to_string(&self.to_value()?)
+    }
+
+    /// JSON indented by four spaces, like the chart files the game ships
+    pub fn to_json_pretty(&self) -> Result<String, serde_json::Error> {
+        let mut json = Vec::new();
+        self.to_writer(&mut json)?;
+        Ok(String::from_utf8(json).expect("serde_json writes UTF-8"))
+    }
+
+    /// Write the chart as `to_json_pretty` formats it
+    pub fn to_writer<W: io::Write>(&self, writer: W) -> Result<(), serde_json::Error> {
+        let mut serializer =
+            Serializer::with_formatter(writer, PrettyFormatter::with_indent(b"    "));
+        self.to_value()?.serialize(&mut serializer)
+    }
+
+    /// The chart as JSON in the key order and number forms of the file it was read from
+    fn to_value(&self) -> Result<Value, serde_json::Error> {
+        let mut value = serde_json::to_value(self)?;
+        self.shape.restore(&mut value);
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use serde_json::{json, Value};
+
+    use super::Chart;
+
+    const SAMPLE: &str = include_str!("../morimoriatsushi0IN.json");
+
+    /// File of a small valid chart at 60 BPM, where ticks are seconds, with
+    /// one line holding `notes`
+    pub(crate) fn test_chart_json(notes: Value) -> Value {
+        let color = json!({ "r": 255, "g": 255, "b": 255, "a": 255 });
+        let key_point = |value: f64| json!({ "time": 0.0, "value": value, "easeType": 0, "floorPosition": 0.0 });
+        let line_point = |time: f64| {
+            json!({
+                "time": time,
+                "xPosition": 0.0,
+                "color": color,
+                "easeType": 0,
+                "canvasIndex": 0,
+                "floorPosition": 0.0
+            })
+        };
+        json!({
+            "fileVersion": 0,
+            "songsName": "test",
+            "themes": [{ "colorsList": [color, color, color] }],
+            "challengeTimes": [],
+            "bPM": 60.0,
+            "bpmShifts": [key_point(1.0)],
+            "offset": 0.0,
+            "lines": [{
+                "linePoints": [line_point(0.0), line_point(100.0)],
+                "notes": notes,
+                "judgeRingColor": [],
+                "lineColor": []
+            }],
+            "canvasMoves": [{
+                "index": 0,
+                "xPositionKeyPoints": [key_point(0.0)],
+                "speedKeyPoints": [key_point(1.0)]
+            }],
+            "cameraMove": {
+                "scaleKeyPoints": [key_point(1.0)],
+                "xPositionKeyPoints": [key_point(0.0)]
+            }
+        })
+    }
+
+    /// `test_chart_json` read as a chart
+    pub(crate) fn test_chart(notes: Value) -> Chart {
+        Chart::from_json(&test_chart_json(notes).to_string()).unwrap()
+    }
+
+    #[test]
+    fn sample_chart_round_trips() {
+        let chart = Chart::from_json(SAMPLE).unwrap();
+        let saved = chart.to_json_pretty().unwrap();
+        assert_eq!(
+            serde_json::from_str::<Value>(&saved).unwrap(),
+            serde_json::from_str::<Value>(SAMPLE).unwrap()
+        );
+        // Byte for byte, as the README promises; the strings are too long to print
+        let differs_at = saved.bytes().zip(SAMPLE.bytes()).position(|(a, b)| a != b);
+        assert!(
+            saved == SAMPLE,
+            "saved chart differs from the file at byte {:?} (lengths {} and {})",
+            differs_at,
+            saved.len(),
+            SAMPLE.len()
+        );
+    }
+
+    #[test]
+    fn unknown_keys_and_integers_keep_their_place() {
+        let mut value: Value = serde_json::from_str(SAMPLE).unwrap();
+        let file = value.as_object_mut().unwrap();
+        file.shift_insert(0, "editor".into(), json!({ "name": "test", "version": 3 }));
+        file.insert("bPM".into(), json!(120));
+        let color = &mut file["themes"][0]["colorsList"][0];
+        color
+            .as_object_mut()
+            .unwrap()
+            .shift_insert(0, "name".into(), json!("background"));
+        let json = serde_json::to_string(&value).unwrap();
+
+        let chart = Chart::from_json(&json).unwrap();
+        assert_eq!(chart.bpm, 120.0);
+        assert_eq!(chart.themes[0].colors_list[0].extra["name"], "background");
+        assert_eq!(chart.to_json().unwrap(), json);
     }
 }
@@ -0,0 +1,331 @@
+//! Command line parsing
+
+use std::path::PathBuf;
+
+use ch_rzl::headless::RenderOutput;
+use ch_rzl::judge::{JudgeWindows, PlayMode};
+
+/// Chart played when no path is given
+pub const DEFAULT_CHART: &str = "morimoriatsushi0IN.json";
+
+pub const USAGE: &str = "\
+Usage:
+  ch-rzl [chart] [song] [play options]
+                                     Play a chart in a window (autoplay by default)
+  ch-rzl render <chart> [song] [options]
+                                     Render a chart to video without a window
+  ch-rzl mix <chart> <song> [-o out.wav] [--hit-sounds <dir>]
+                                     Mix the chart's hit sounds into the song (default: output.wav)
+  ch-rzl score <chart> <replay>      Judge a replay and print its score as JSON
+  ch-rzl check <chart> [--json]      Check a chart for problems (exits non-zero on errors)
+
+Play options:
+  --play                 Hit the notes yourself: A S D F J K L ; keys, mouse or touch
+  --perfect <ms>         Perfect window on each side of a note (default: 45)
+  --good <ms>            Good window on each side of a note (default: 90)
+  --watermark <text>     Watermark line drawn over the chart
+  --result <file>        Write the score as JSON to <file> when the song ends
+  --record <file>        Record your input to a replay file (implies --play)
+  --replay <file>        Play back a replay file instead of your input
+  --hand                 Show the autoplay hand moving between notes
+  --hand-sprite <image>  Draw the autoplay hand with <image> (implies --hand)
+
+Render options:
+  -o, --output <file>    Pipe raw frames to ffmpeg and encode <file> (default: output.mp4)
+  --png <dir>            Write a PNG image sequence to <dir> instead
+  --size <W>x<H>         Output resolution (default: 1080x1920)
+  --fps <n>              Frame rate (default: 60)
+  --start <seconds>      Start time (default: 0)
+  --duration <seconds>   Length to render (default: song or chart length)
+  --watermark <text>     Watermark line drawn over the chart
+  --replay <file>        Render a replay instead of autoplay
+  --hand                 Show the autoplay hand moving between notes
+  --hand-sprite <image>  Draw the autoplay hand with <image> (implies --hand)";
+
+#[derive(Debug, Clone)]
+pub struct PlayOptions {
+    pub chart: PathBuf,
+    pub song: Option<PathBuf>,
+    pub mode: PlayMode,
+    pub windows: JudgeWindows,
+    pub watermark: Option<String>,
+    pub result: Option<PathBuf>,
+    pub record: Option<PathBuf>,
+    pub replay: Option<PathBuf>,
+    pub hand: bool,
+    pub hand_sprite: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone)]
+pub struct RenderOptions {
+    pub chart: PathBuf,
+    pub song: Option<PathBuf>,
+    pub output: RenderOutput,
+    pub width: u32,
+    pub height: u32,
+    pub fps: u32,
+    pub start: f64,
+    pub duration: Option<f64>,
+    pub watermark: Option<String>,
+    pub replay: Option<PathBuf>,
+    pub hand: bool,
+    pub hand_sprite: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone)]
+pub struct MixOptions {
+    pub chart: PathBuf,
+    pub song: PathBuf,
+    pub output: PathBuf,
+    /// Directory with hit.wav and drag.wav, found automatically if not given
+    pub hit_sounds: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ScoreOptions {
+    pub chart: PathBuf,
+    pub replay: PathBuf,
+}
+
+#[derive(Debug, Clone)]
+pub struct CheckOptions {
+    pub chart: PathBuf,
+    /// Print the diagnostics as JSON instead of text
+    pub json: bool,
+}
+
+#[derive(Debug, Clone)]
+pub enum Command {
+    Play(PlayOptions),
+    Render(RenderOptions),
+    Mix(MixOptions),
+    Score(ScoreOptions),
+    Check(CheckOptions),
+}
+
+/// Parse the arguments following the program name
+pub fn parse_args(args: &[String]) -> Result<Command, String> {
+    match args.first().map(String::as_str) {
+        Some("render") => parse_render(&args[1..]).map(Command::Render),
+        Some("mix") => parse_mix(&args[1..]).map(Command::Mix),
+        Some("score") => parse_score(&args[1..]).map(Command::Score),
+        Some("check") => parse_check(&args[1..]).map(Command::Check),
+        Some("-h") | Some("--help") => Err(String::new()),
+        _ => parse_play(args).map(Command::Play),
+    }
+}
+
+fn parse_play(args: &[String]) -> Result<PlayOptions, String> {
+    let mut positional = Vec::new();
+    let mut mode = PlayMode::Autoplay;
+    let mut windows = JudgeWindows::default();
+    let mut watermark = None;
+    let mut result = None;
+    let mut record = None;
+    let mut replay = None;
+    let mut hand = false;
+    let mut hand_sprite = None;
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        let mut value = || {
+            iter.next()
+                .ok_or_else(|| format!("missing value for {}", arg))
+        };
+        match arg.as_str() {
+            "--play" => mode = PlayMode::Play,
+            "--perfect" => windows.perfect = parse_number::<f64>(arg, value()?)? / 1000.0,
+            "--good" => windows.good = parse_number::<f64>(arg, value()?)? / 1000.0,
+            "--watermark" => watermark = Some(value()?.clone()),
+            "--result" => result = Some(PathBuf::from(value()?)),
+            "--record" => record = Some(PathBuf::from(value()?)),
+            "--replay" => replay = Some(PathBuf::from(value()?)),
+            "--hand" => hand = true,
+            "--hand-sprite" => hand_sprite = Some(PathBuf::from(value()?)),
+            flag if flag.starts_with('-') => return Err(format!("unknown option {}", flag)),
+            _ => positional.push(PathBuf::from(arg)),
+        }
+    }
+
+    if !(0.0 < windows.perfect && windows.perfect <= windows.good) {
+        return Err("judgement windows must satisfy 0 < --perfect <= --good".to_string());
+    }
+    if record.is_some() && replay.is_some() {
+        return Err("--record and --replay cannot be used together".to_string());
+    }
+    if record.is_some() || replay.is_some() {
+        mode = PlayMode::Play;
+    }
+
+    let mut positional = positional.into_iter();
+    let chart = positional
+        .next()
+        .unwrap_or_else(|| PathBuf::from(DEFAULT_CHART));
+    let song = positional.next();
+    if let Some(extra) = positional.next() {
+        return Err(format!("unexpected argument {}", extra.display()));
+    }
+
+    Ok(PlayOptions {
+        chart,
+        song,
+        mode,
+        windows,
+        watermark,
+        result,
+        record,
+        replay,
+        hand: hand || hand_sprite.is_some(),
+        hand_sprite,
+    })
+}
+
+fn parse_render(args: &[String]) -> Result<RenderOptions, String> {
+    let mut positional = Vec::new();
+    let mut output = RenderOutput::Ffmpeg(PathBuf::from("output.mp4"));
+    let mut width = 1080;
+    let mut height = 1920;
+    let mut fps = 60;
+    let mut start = 0.0;
+    let mut duration = None;
+    let mut watermark = None;
+    let mut replay = None;
+    let mut hand = false;
+    let mut hand_sprite = None;
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        let mut value = || {
+            iter.next()
+                .ok_or_else(|| format!("missing value for {}", arg))
+        };
+        match arg.as_str() {
+            "-o" | "--output" => output = RenderOutput::Ffmpeg(PathBuf::from(value()?)),
+            "--png" => output = RenderOutput::PngSequence(PathBuf::from(value()?)),
+            "--size" => (width, height) = parse_size(value()?)?,
+            "--fps" => fps = parse_number(arg, value()?)?,
+            "--start" => start = parse_number(arg, value()?)?,
+            "--duration" => duration = Some(parse_number(arg, value()?)?),
+            "--watermark" => watermark = Some(value()?.clone()),
+            "--replay" => replay = Some(PathBuf::from(value()?)),
+            "--hand" => hand = true,
+            "--hand-sprite" => hand_sprite = Some(PathBuf::from(value()?)),
+            flag if flag.starts_with('-') => return Err(format!("unknown option {}", flag)),
+            _ => positional.push(PathBuf::from(arg)),
+        }
+    }
+
+    if fps == 0 {
+        return Err("--fps must be positive".to_string());
+    }
+
+    let mut positional = positional.into_iter();
+    let chart = positional.next().ok_or("missing chart path")?;
+    let song = positional.next();
+    if let Some(extra) = positional.next() {
+        return Err(format!("unexpected argument {}", extra.display()));
+    }
+
+    Ok(RenderOptions {
+        chart,
+        song,
+        output,
+        width,
+        height,
+        fps,
+        start,
+        duration,
+        watermark,
+        replay,
+        hand: hand || hand_sprite.is_some(),
+        hand_sprite,
+    })
+}
+
+fn parse_mix(args: &[String]) -> Result<MixOptions, String> {
+    let mut positional = Vec::new();
+    let mut output = PathBuf::from("output.wav");
+    let mut hit_sounds = None;
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        let mut value = || {
+            iter.next()
+                .ok_or_else(|| format!("missing value for {}", arg))
+        };
+        match arg.as_str() {
+            "-o" | "--output" => output = PathBuf::from(value()?),
+            "--hit-sounds" => hit_sounds = Some(PathBuf::from(value()?)),
+            flag if flag.starts_with('-') => return Err(format!("unknown option {}", flag)),
+            _ => positional.push(PathBuf::from(arg)),
+        }
+    }
+
+    let mut positional = positional.into_iter();
+    let chart = positional.next().ok_or("missing chart path")?;
+    let song = positional.next().ok_or("missing song path")?;
+    if let Some(extra) = positional.next() {
+        return Err(format!("unexpected argument {}", extra.display()));
+    }
+
+    Ok(MixOptions {
+        chart,
+        song,
+        output,
+        hit_sounds,
+    })
+}
+
+fn parse_score(args: &[String]) -> Result<ScoreOptions, String> {
+    if let Some(flag) = args.iter().find(|arg| arg.starts_with('-')) {
+        return Err(format!("unknown option {}", flag));
+    }
+
+    let mut positional = args.iter().map(PathBuf::from);
+    let chart = positional.next().ok_or("missing chart path")?;
+    let replay = positional.next().ok_or("missing replay path")?;
+    if let Some(extra) = positional.next() {
+        return Err(format!("unexpected argument {}", extra.display()));
+    }
+
+    Ok(ScoreOptions { chart, replay })
+}
+
+fn parse_check(args: &[String]) -> Result<CheckOptions, String> {
+    let mut positional = Vec::new();
+    let mut json = false;
+
+    for arg in args {
+        match arg.as_str() {
+            "--json" => json = true,
+            flag if flag.starts_with('-') => return Err(format!("unknown option {}", flag)),
+            _ => positional.push(PathBuf::from(arg)),
+        }
+    }
+
+    let mut positional = positional.into_iter();
+    let chart = positional.next().ok_or("missing chart path")?;
+    if let Some(extra) = positional.next() {
+        return Err(format!("unexpected argument {}", extra.display()));
+    }
+
+    Ok(CheckOptions { chart, json })
+}
+
+fn parse_number<T: std::str::FromStr>(flag: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("invalid value for {}: {}", flag, value))
+}
+
+/// Parse `WIDTHxHEIGHT`
+fn parse_size(value: &str) -> Result<(u32, u32), String> {
+    let invalid = || format!("invalid size {}, expected WIDTHxHEIGHT", value);
+    let (w, h) = value.split_once(['x', 'X']).ok_or_else(invalid)?;
+    let width: u32 = w.parse().map_err(|_| invalid())?;
+    let height: u32 = h.parse().map_err(|_| invalid())?;
+    if width == 0 || height == 0 {
+        return Err(invalid());
+    }
+    Ok((width, height))
+}
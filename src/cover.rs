@@ -0,0 +1,95 @@
+//! Fade cover at the top and bottom of the playfield, like `drawCover` in
+//! the JS player: stacked bands of the theme background color with rising
+//! opacity, so notes fade in and out instead of popping at the screen edge.
+
+use bevy::prelude::*;
+
+use crate::game::GameState;
+use crate::rendering::{place_quad, quad_bundle, GameEntity, Playfield, Quad, RenderHandles};
+
+/// Bands per edge; the JS player steps the opacity from 0 to 5 by 0.2
+pub const COVER_BANDS: usize = 25;
+
+/// Opacity step between two bands
+const COVER_ALPHA_STEP: f32 = 0.2;
+
+/// Depth of the cover, above notes and below hit effects
+const COVER_Z: f32 = 3.5;
+
+/// Edge of the playfield covered by a band
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum CoverEdge {
+    Top,
+    Bottom,
+}
+
+/// One band of the cover
+#[derive(Component, Clone, Copy)]
+pub struct CoverBand {
+    pub edge: CoverEdge,
+    pub step: usize,
+}
+
+impl CoverBand {
+    /// Quad of the band, laid out like the JS `fillRect` calls (in units of `cvs.height / 640`)
+    fn quad(self, playfield: &Playfield, color: Color) -> Quad {
+        let unit = playfield.height / 640.0;
+        let height = 30.0 * unit;
+        let i = self.step as f32 * COVER_ALPHA_STEP;
+        // JS y of the band's top edge, with y pointing down
+        let js_top = match self.edge {
+            CoverEdge::Top => -playfield.height / 2.0 - (170.0 * unit + height * i),
+            CoverEdge::Bottom => playfield.height / 2.0 - 300.0 * unit + height * i,
+        };
+        Quad::new(
+            Vec2::new(0.0, -(js_top + height / 2.0)),
+            Vec2::new(playfield.width * 2.0, height),
+            COVER_Z,
+            color.with_alpha(i.min(1.0)),
+        )
+    }
+}
+
+/// Spawn the hidden bands of a newly inserted chart
+pub fn spawn_cover(
+    mut commands: Commands,
+    handles: Res<RenderHandles>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    for edge in [CoverEdge::Top, CoverEdge::Bottom] {
+        for step in 0..COVER_BANDS {
+            commands.spawn((
+                quad_bundle(&handles, materials.add(Color::NONE)),
+                GameEntity,
+                CoverBand { edge, step },
+            ));
+        }
+    }
+}
+
+/// Color the cover with the current theme background; hidden in the revelation view
+pub fn update_cover(
+    game_state: Res<GameState>,
+    playfield: Res<Playfield>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut bands: Query<(
+        &CoverBand,
+        &mut Transform,
+        &mut Visibility,
+        &Handle<ColorMaterial>,
+    )>,
+) {
+    let revelation = game_state.is_revelation();
+    let color = game_state.get_theme_color(0).to_bevy_color();
+
+    for (band, mut transform, mut visibility, material) in bands.iter_mut() {
+        let quad = (!revelation).then(|| band.quad(&playfield, color));
+        place_quad(
+            quad,
+            &mut transform,
+            &mut visibility,
+            material,
+            &mut materials,
+        );
+    }
+}
@@ -1,5 +1,22 @@
-/// Easing functions for animations
-/// Matches the 19 easing types from the original JavaScript implementation
+//! Easing functions for animations
+//! IDs 0-18 are the 19 easing types of js/ease.js, the only `easeType`s charts
+//! are known to use. The rest of the standard set (sine in-out, expo, back,
+//! elastic, bounce) is in the `EASES` registry without an ID, until a source
+//! for the IDs newer game versions give them turns up; charts reach them by
+//! name through `CustomEase::Named`.
+//!
+//! As js/formatCheck.js rewrites unknown `easeType`s to 0 before playing, and
+//! js/format.js falls back to `easeFuncs[0]` for line points, unknown IDs ease
+//! linearly here. (`tweenExecute` of js/ease.js, which the player does not
+//! call, jumps to the end value instead.)
+//!
+//! `CustomEase` covers curves of other chart formats (RPE/Phigros-style) that
+//! no ID stands for: cubic beziers and sub-ranges of a built-in ease.
+
+use std::f32::consts::PI;
+use std::fmt;
+
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
 
 pub fn linear(x: f32) -> f32 {
     x
@@ -86,41 +103,520 @@ pub fn ease_out_circ(x: f32) -> f32 {
 }
 
 pub fn ease_out_sine(x: f32) -> f32 {
-    (x * std::f32::consts::PI / 2.0).sin()
+    (x * PI / 2.0).sin()
 }
 
 pub fn ease_in_sine(x: f32) -> f32 {
-    1.0 - (x * std::f32::consts::PI / 2.0).cos()
+    1.0 - (x * PI / 2.0).cos()
+}
+
+pub fn ease_in_out_sine(x: f32) -> f32 {
+    -((PI * x).cos() - 1.0) / 2.0
+}
+
+pub fn ease_in_out_circ(x: f32) -> f32 {
+    if x < 0.5 {
+        (1.0 - (1.0 - (2.0 * x).powi(2)).sqrt()) / 2.0
+    } else {
+        ((1.0 - (-2.0 * x + 2.0).powi(2)).sqrt() + 1.0) / 2.0
+    }
+}
+
+pub fn ease_in_expo(x: f32) -> f32 {
+    if x == 0.0 {
+        0.0
+    } else {
+        2.0_f32.powf(10.0 * x - 10.0)
+    }
+}
+
+pub fn ease_out_expo(x: f32) -> f32 {
+    if x == 1.0 {
+        1.0
+    } else {
+        1.0 - 2.0_f32.powf(-10.0 * x)
+    }
+}
+
+pub fn ease_in_out_expo(x: f32) -> f32 {
+    if x == 0.0 || x == 1.0 {
+        x
+    } else if x < 0.5 {
+        2.0_f32.powf(20.0 * x - 10.0) / 2.0
+    } else {
+        (2.0 - 2.0_f32.powf(-20.0 * x + 10.0)) / 2.0
+    }
+}
+
+/// Overshoot of the back eases
+const BACK_C1: f32 = 1.70158;
+const BACK_C2: f32 = BACK_C1 * 1.525;
+const BACK_C3: f32 = BACK_C1 + 1.0;
+
+pub fn ease_in_back(x: f32) -> f32 {
+    BACK_C3 * x * x * x - BACK_C1 * x * x
+}
+
+/// `easeOutBack` of js/hyperOSmsg.js
+pub fn ease_out_back(x: f32) -> f32 {
+    1.0 + BACK_C3 * (x - 1.0).powi(3) + BACK_C1 * (x - 1.0).powi(2)
+}
+
+pub fn ease_in_out_back(x: f32) -> f32 {
+    if x < 0.5 {
+        (2.0 * x).powi(2) * ((BACK_C2 + 1.0) * 2.0 * x - BACK_C2) / 2.0
+    } else {
+        ((2.0 * x - 2.0).powi(2) * ((BACK_C2 + 1.0) * (x * 2.0 - 2.0) + BACK_C2) + 2.0) / 2.0
+    }
+}
+
+/// Periods of the elastic eases
+const ELASTIC_C4: f32 = 2.0 * PI / 3.0;
+const ELASTIC_C5: f32 = 2.0 * PI / 4.5;
+
+pub fn ease_in_elastic(x: f32) -> f32 {
+    if x == 0.0 || x == 1.0 {
+        x
+    } else {
+        -(2.0_f32.powf(10.0 * x - 10.0)) * ((x * 10.0 - 10.75) * ELASTIC_C4).sin()
+    }
+}
+
+pub fn ease_out_elastic(x: f32) -> f32 {
+    if x == 0.0 || x == 1.0 {
+        x
+    } else {
+        2.0_f32.powf(-10.0 * x) * ((x * 10.0 - 0.75) * ELASTIC_C4).sin() + 1.0
+    }
+}
+
+pub fn ease_in_out_elastic(x: f32) -> f32 {
+    if x == 0.0 || x == 1.0 {
+        x
+    } else if x < 0.5 {
+        -(2.0_f32.powf(20.0 * x - 10.0) * ((20.0 * x - 11.125) * ELASTIC_C5).sin()) / 2.0
+    } else {
+        2.0_f32.powf(-20.0 * x + 10.0) * ((20.0 * x - 11.125) * ELASTIC_C5).sin() / 2.0 + 1.0
+    }
+}
+
+pub fn ease_out_bounce(x: f32) -> f32 {
+    const N1: f32 = 7.5625;
+    const D1: f32 = 2.75;
+    if x < 1.0 / D1 {
+        N1 * x * x
+    } else if x < 2.0 / D1 {
+        let x = x - 1.5 / D1;
+        N1 * x * x + 0.75
+    } else if x < 2.5 / D1 {
+        let x = x - 2.25 / D1;
+        N1 * x * x + 0.9375
+    } else {
+        let x = x - 2.625 / D1;
+        N1 * x * x + 0.984375
+    }
+}
+
+pub fn ease_in_bounce(x: f32) -> f32 {
+    1.0 - ease_out_bounce(1.0 - x)
+}
+
+pub fn ease_in_out_bounce(x: f32) -> f32 {
+    if x < 0.5 {
+        (1.0 - ease_out_bounce(1.0 - 2.0 * x)) / 2.0
+    } else {
+        (1.0 + ease_out_bounce(2.0 * x - 1.0)) / 2.0
+    }
+}
+
+/// Built-in ease; the first 19 are the ease types of `easeType` fields, by ID
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(u8)]
+pub enum Ease {
+    Linear = 0,
+    InQuad = 1,
+    OutQuad = 2,
+    InOutQuad = 3,
+    InCubic = 4,
+    OutCubic = 5,
+    InOutCubic = 6,
+    InQuart = 7,
+    OutQuart = 8,
+    InOutQuart = 9,
+    InQuint = 10,
+    OutQuint = 11,
+    InOutQuint = 12,
+    Zero = 13,
+    One = 14,
+    InCirc = 15,
+    OutCirc = 16,
+    OutSine = 17,
+    InSine = 18,
+    // No chart ID
+    InOutSine,
+    InOutCirc,
+    InExpo,
+    OutExpo,
+    InOutExpo,
+    InBack,
+    OutBack,
+    InOutBack,
+    InElastic,
+    OutElastic,
+    InOutElastic,
+    InBounce,
+    OutBounce,
+    InOutBounce,
+}
+
+/// Registry entry of a built-in ease
+pub struct EaseEntry {
+    pub ease: Ease,
+    /// `easeType` of the ease in charts, if it has one
+    pub id: Option<u8>,
+    /// Name as in js/ease.js, or as in the usual easing naming for eases without an ID
+    pub name: &'static str,
+    pub function: fn(f32) -> f32,
+}
+
+const fn entry(ease: Ease, id: u8, name: &'static str, function: fn(f32) -> f32) -> EaseEntry {
+    EaseEntry {
+        ease,
+        id: Some(id),
+        name,
+        function,
+    }
+}
+
+const fn unnumbered(ease: Ease, name: &'static str, function: fn(f32) -> f32) -> EaseEntry {
+    EaseEntry {
+        ease,
+        id: None,
+        name,
+        function,
+    }
+}
+
+/// Every built-in ease, those with an ID first and at the index of their ID.
+/// New eases are appended, so IDs given to them later never move others.
+pub const EASES: [EaseEntry; 33] = [
+    entry(Ease::Linear, 0, "linear", linear),
+    entry(Ease::InQuad, 1, "easeInQuad", ease_in_quad),
+    entry(Ease::OutQuad, 2, "easeOutQuad", ease_out_quad),
+    entry(Ease::InOutQuad, 3, "easeInOutQuad", ease_in_out_quad),
+    entry(Ease::InCubic, 4, "easeInCubic", ease_in_cubic),
+    entry(Ease::OutCubic, 5, "easeOutCubic", ease_out_cubic),
+    entry(Ease::InOutCubic, 6, "easeInOutCubic", ease_in_out_cubic),
+    entry(Ease::InQuart, 7, "easeInQuart", ease_in_quart),
+    entry(Ease::OutQuart, 8, "easeOutQuart", ease_out_quart),
+    entry(Ease::InOutQuart, 9, "easeInOutQuart", ease_in_out_quart),
+    entry(Ease::InQuint, 10, "easeInQuint", ease_in_quint),
+    entry(Ease::OutQuint, 11, "easeOutQuint", ease_out_quint),
+    entry(Ease::InOutQuint, 12, "easeInOutQuint", ease_in_out_quint),
+    entry(Ease::Zero, 13, "easeZero", ease_zero),
+    entry(Ease::One, 14, "easeOne", ease_one),
+    entry(Ease::InCirc, 15, "easeInCirc", ease_in_circ),
+    entry(Ease::OutCirc, 16, "easeOutCirc", ease_out_circ),
+    entry(Ease::OutSine, 17, "easeOutSine", ease_out_sine),
+    entry(Ease::InSine, 18, "easeInSine", ease_in_sine),
+    unnumbered(Ease::InOutSine, "easeInOutSine", ease_in_out_sine),
+    unnumbered(Ease::InOutCirc, "easeInOutCirc", ease_in_out_circ),
+    unnumbered(Ease::InExpo, "easeInExpo", ease_in_expo),
+    unnumbered(Ease::OutExpo, "easeOutExpo", ease_out_expo),
+    unnumbered(Ease::InOutExpo, "easeInOutExpo", ease_in_out_expo),
+    unnumbered(Ease::InBack, "easeInBack", ease_in_back),
+    unnumbered(Ease::OutBack, "easeOutBack", ease_out_back),
+    unnumbered(Ease::InOutBack, "easeInOutBack", ease_in_out_back),
+    unnumbered(Ease::InElastic, "easeInElastic", ease_in_elastic),
+    unnumbered(Ease::OutElastic, "easeOutElastic", ease_out_elastic),
+    unnumbered(Ease::InOutElastic, "easeInOutElastic", ease_in_out_elastic),
+    unnumbered(Ease::InBounce, "easeInBounce", ease_in_bounce),
+    unnumbered(Ease::OutBounce, "easeOutBounce", ease_out_bounce),
+    unnumbered(Ease::InOutBounce, "easeInOutBounce", ease_in_out_bounce),
+];
+
+// Every entry sits at the index of its ease, and of its ID if it has one
+const _: () = {
+    let mut i = 0;
+    while i < EASES.len() {
+        assert!(EASES[i].ease as usize == i);
+        if let Some(id) = EASES[i].id {
+            assert!(id as usize == i);
+        }
+        i += 1;
+    }
+};
+
+impl Ease {
+    /// `easeType` of the ease in charts, if it has one
+    pub fn id(self) -> Option<u8> {
+        EASES[self as usize].id
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        EASES
+            .iter()
+            .find(|entry| entry.name == name)
+            .map(|entry| entry.ease)
+    }
+
+    pub fn name(self) -> &'static str {
+        EASES[self as usize].name
+    }
+
+    pub fn apply(self, t: f32) -> f32 {
+        (EASES[self as usize].function)(t.clamp(0.0, 1.0))
+    }
+}
+
+impl fmt::Display for Ease {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(self.name())
+    }
 }
 
-/// Get easing function by type index (0-18)
+/// Eases are written by name, as the IDs do not cover all of them
+impl Serialize for Ease {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.name())
+    }
+}
+
+impl<'de> Deserialize<'de> for Ease {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        Ease::from_name(&name).ok_or_else(|| de::Error::custom(format!("unknown ease {}", name)))
+    }
+}
+
+/// Ease type ID no entry of `EASES` has
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UnknownEase(pub u8);
+
+impl fmt::Display for UnknownEase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let last = EASES
+            .iter()
+            .filter_map(|entry| entry.id)
+            .max()
+            .unwrap_or_default();
+        write!(
+            f,
+            "unknown ease type {}, known types are 0-{}",
+            self.0, last
+        )
+    }
+}
+
+impl std::error::Error for UnknownEase {}
+
+impl TryFrom<u8> for Ease {
+    type Error = UnknownEase;
+
+    fn try_from(id: u8) -> Result<Self, Self::Error> {
+        EASES
+            .iter()
+            .find(|entry| entry.id == Some(id))
+            .map(|entry| entry.ease)
+            .ok_or(UnknownEase(id))
+    }
+}
+
+/// Get easing function by type ID; unknown IDs ease linearly
 pub fn get_ease_func(ease_type: u8) -> fn(f32) -> f32 {
-    match ease_type {
-        0 => linear,
-        1 => ease_in_quad,
-        2 => ease_out_quad,
-        3 => ease_in_out_quad,
-        4 => ease_in_cubic,
-        5 => ease_out_cubic,
-        6 => ease_in_out_cubic,
-        7 => ease_in_quart,
-        8 => ease_out_quart,
-        9 => ease_in_out_quart,
-        10 => ease_in_quint,
-        11 => ease_out_quint,
-        12 => ease_in_out_quint,
-        13 => ease_zero,
-        14 => ease_one,
-        15 => ease_in_circ,
-        16 => ease_out_circ,
-        17 => ease_out_sine,
-        18 => ease_in_sine,
-        _ => linear,
-    }
-}
-
-/// Apply easing to interpolate between two values
+    Ease::try_from(ease_type).map_or(linear, |ease| EASES[ease as usize].function)
+}
+
+/// Apply easing to interpolate between two values; unknown IDs ease linearly
 pub fn apply_ease(ease_type: u8, t: f32) -> f32 {
-    let ease_fn = get_ease_func(ease_type);
-    ease_fn(t.clamp(0.0, 1.0))
+    Ease::try_from(ease_type).unwrap_or(Ease::Linear).apply(t)
+}
+
+/// Bisection steps solving a bezier's x for its parameter, enough for f64 precision
+const BEZIER_STEPS: usize = 60;
+
+/// Ease without an ID, kept in the `customEase` field of keypoints and line
+/// points, which vanilla Rizline ignores in favour of their `easeType`
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum CustomEase {
+    /// CSS-style `cubic-bezier(x1, y1, x2, y2)` from (0, 0) to (1, 1)
+    Bezier { points: [f64; 4] },
+    /// Part `left..right` of a built-in ease, rescaled to run from 0 to 1
+    #[serde(rename_all = "camelCase")]
+    Range {
+        ease_type: u8,
+        left: f64,
+        right: f64,
+    },
+    /// Built-in ease by name, for the eases without an ID
+    Named { ease: Ease },
+}
+
+impl CustomEase {
+    pub fn apply(&self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0) as f64;
+        match *self {
+            CustomEase::Bezier {
+                points: [x1, y1, x2, y2],
+            } => bezier(x1, y1, x2, y2, t) as f32,
+            CustomEase::Range {
+                ease_type,
+                left,
+                right,
+            } => {
+                let ease = |x: f64| apply_ease(ease_type, x as f32) as f64;
+                let (from, to) = (ease(left), ease(right));
+                let value = ease(left + (right - left) * t);
+                if (to - from).abs() > f64::EPSILON {
+                    ((value - from) / (to - from)) as f32
+                } else {
+                    // A flat range, like one of `Zero` or `One`, stays at its value
+                    value as f32
+                }
+            }
+            CustomEase::Named { ease } => ease.apply(t as f32),
+        }
+    }
+}
+
+/// y of the cubic bezier through (0, 0), (x1, y1), (x2, y2), (1, 1) where its x is `x`;
+/// x is monotonic as long as `x1` and `x2` lie in 0..=1
+fn bezier(x1: f64, y1: f64, x2: f64, y2: f64, x: f64) -> f64 {
+    let curve = |a: f64, b: f64, s: f64| {
+        3.0 * a * s * (1.0 - s).powi(2) + 3.0 * b * s * s * (1.0 - s) + s.powi(3)
+    };
+    let (mut low, mut high) = (0.0, 1.0);
+    for _ in 0..BEZIER_STEPS {
+        let middle = (low + high) / 2.0;
+        if curve(x1, x2, middle) < x {
+            low = middle;
+        } else {
+            high = middle;
+        }
+    }
+    curve(y1, y2, (low + high) / 2.0)
+}
+
+/// Ease of a keyframe: its custom ease if it has one, else its ease type
+pub fn keyframe_ease(ease_type: u8, custom_ease: Option<&CustomEase>, t: f32) -> f32 {
+    match custom_ease {
+        Some(custom_ease) => custom_ease.apply(t),
+        None => apply_ease(ease_type, t),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::chart::tests::test_chart_json;
+    use crate::chart::Chart;
+
+    /// `t` from 0 to 1 in `steps` steps
+    fn samples(steps: usize) -> impl Iterator<Item = f32> {
+        (0..=steps).map(move |i| i as f32 / steps as f32)
+    }
+
+    #[test]
+    fn chart_ids_are_0_to_18() {
+        for id in 0..=18 {
+            let ease = Ease::try_from(id).unwrap();
+            assert_eq!(ease.id(), Some(id));
+        }
+        assert_eq!(Ease::try_from(19), Err(UnknownEase(19)));
+        assert_eq!(Ease::try_from(u8::MAX), Err(UnknownEase(u8::MAX)));
+        assert!(EASES[19..].iter().all(|entry| entry.id.is_none()));
+    }
+
+    #[test]
+    fn eases_run_from_0_to_1() {
+        for entry in &EASES {
+            let (start, end) = (entry.ease.apply(0.0), entry.ease.apply(1.0));
+            // easeZero and easeOne of js/ease.js hold their value throughout
+            let expected = match entry.ease {
+                Ease::Zero => (0.0, 0.0),
+                Ease::One => (1.0, 1.0),
+                _ => (0.0, 1.0),
+            };
+            assert!(
+                (start - expected.0).abs() < 1e-6 && (end - expected.1).abs() < 1e-6,
+                "{} runs from {} to {}",
+                entry.name,
+                start,
+                end
+            );
+        }
+    }
+
+    #[test]
+    fn bezier_runs_from_0_to_1() {
+        for points in [
+            [0.42, 0.0, 0.58, 1.0],
+            [0.0, 0.0, 1.0, 1.0],
+            [0.1, 0.9, 0.2, 1.0],
+        ] {
+            let ease = CustomEase::Bezier { points };
+            assert!(ease.apply(0.0).abs() < 1e-6, "{:?}", points);
+            assert!((ease.apply(1.0) - 1.0).abs() < 1e-6, "{:?}", points);
+        }
+    }
+
+    #[test]
+    fn bezier_with_control_points_in_the_unit_square_is_monotonic() {
+        for points in [
+            [0.42, 0.0, 0.58, 1.0],
+            [0.9, 0.1, 0.1, 0.9],
+            [0.0, 1.0, 1.0, 0.0],
+        ] {
+            let ease = CustomEase::Bezier { points };
+            let values: Vec<f32> = samples(200).map(|t| ease.apply(t)).collect();
+            assert!(
+                values.windows(2).all(|pair| pair[1] >= pair[0] - 1e-6),
+                "{:?}",
+                points
+            );
+        }
+    }
+
+    #[test]
+    fn range_rescales_part_of_an_ease_to_0_1() {
+        let ease = CustomEase::Range {
+            ease_type: Ease::InQuad as u8,
+            left: 0.2,
+            right: 0.8,
+        };
+        assert!(ease.apply(0.0).abs() < 1e-6);
+        assert!((ease.apply(1.0) - 1.0).abs() < 1e-6);
+        // Halfway is x = 0.5 of easeInQuad, between its values at 0.2 and 0.8
+        let expected = (0.25 - 0.04) / (0.64 - 0.04);
+        assert!((ease.apply(0.5) - expected).abs() < 1e-6);
+    }
+
+    #[test]
+    fn named_ease_applies_the_ease() {
+        let ease: CustomEase =
+            serde_json::from_value(json!({ "type": "named", "ease": "easeOutBounce" })).unwrap();
+        assert_eq!(
+            ease,
+            CustomEase::Named {
+                ease: Ease::OutBounce
+            }
+        );
+        assert_eq!(ease.apply(0.3), ease_out_bounce(0.3));
+    }
+
+    #[test]
+    fn unknown_named_ease_is_rejected_on_load() {
+        let mut file = test_chart_json(json!([]));
+        file["lines"][0]["linePoints"][0]["customEase"] =
+            json!({ "type": "named", "ease": "easeSideways" });
+        let error = Chart::from_json(&file.to_string()).unwrap_err();
+        assert!(
+            error.to_string().contains("unknown ease easeSideways"),
+            "{}",
+            error
+        );
+    }
 }
@@ -0,0 +1,300 @@
+//! Hit effects: an expanding square outline and a few flying blocks drawn
+//! at the judge ring for `HIT_EFFECT_DURATION` seconds after each hit.
+//!
+//! Effects are a pure function of `GameState::current_time`: every frame the
+//! hits within the last `HIT_EFFECT_DURATION` seconds are looked up in the
+//! `HitTimeline`. Seeking backward removes effects and seeking forward (or
+//! starting an offline render mid-song) shows them at their correct phase.
+//! In play mode the hits made by the player are used instead of the chart.
+
+use std::collections::HashSet;
+
+use bevy::prelude::*;
+
+use crate::chart::Color as ChartColor;
+use crate::easing::{ease_in_quint, ease_out_quint};
+use crate::game::GameState;
+use crate::judge::{Judge, PlayMode};
+use crate::rendering::{
+    place_quad, quad_bundle, ring_side, GameEntity, NoteEntity, Playfield, Quad, RenderHandles,
+    RingSide,
+};
+
+/// Lifetime of a hit effect in seconds
+pub const HIT_EFFECT_DURATION: f64 = 0.5;
+
+/// Depth of hit effects, above notes
+const HIT_EFFECT_Z: f32 = 4.0;
+
+/// Hit effect of one note; its pieces are children of this entity
+#[derive(Component)]
+pub struct HitEffect {
+    pub start_time: f64,
+    /// Judge ring x at the hit, in world units
+    pub x: f32,
+    pub color: ChartColor,
+}
+
+impl HitEffect {
+    /// Progress through the effect, in `[0, 1)` while it is alive
+    fn phase(&self, time: f64) -> f64 {
+        (time - self.start_time) / HIT_EFFECT_DURATION
+    }
+}
+
+/// Piece of a hit effect, placed relative to the effect
+#[derive(Component, Clone, Copy)]
+pub enum EffectPiece {
+    Ring(RingSide),
+    /// Block flying out at `angle` (radians), `size` wide at camera scale 1
+    Block {
+        angle: f32,
+        size: f32,
+    },
+}
+
+impl EffectPiece {
+    /// Quad of the piece at `phase`, relative to the effect center
+    fn quad(self, phase: f32, scale: f32, color: ChartColor) -> Quad {
+        let color = color.to_bevy_color();
+        let spread = ease_out_quint(phase);
+        match self {
+            EffectPiece::Ring(side) => {
+                let size = (30.0 + 70.0 * spread) * scale;
+                let line_width = 30.0 * (1.0 - spread) * scale;
+                let ring = Quad::new(Vec2::ZERO, Vec2::splat(size), 0.0, color);
+                ring_side(ring, side, line_width)
+            }
+            EffectPiece::Block { angle, size } => {
+                let fade = 1.0 - ease_in_quint(phase);
+                let offset = Vec2::from_angle(angle) * spread * 100.0 * scale;
+                Quad::new(
+                    offset,
+                    Vec2::splat(size * scale * fade),
+                    0.1,
+                    color.with_alpha(fade),
+                )
+            }
+        }
+    }
+}
+
+/// Hit time of a note
+#[derive(Clone, Copy)]
+pub struct HitTime {
+    pub seconds: f64,
+    pub tick: f64,
+    pub note: NoteEntity,
+}
+
+/// Hit times of every note of the chart, sorted by time
+#[derive(Resource, Default)]
+pub struct HitTimeline {
+    pub hits: Vec<HitTime>,
+}
+
+impl HitTimeline {
+    pub fn new(game_state: &GameState) -> Self {
+        let timing = &game_state.timing;
+        let mut hits: Vec<HitTime> = game_state
+            .chart
+            .lines
+            .iter()
+            .enumerate()
+            .flat_map(|(line_index, line)| {
+                line.notes
+                    .iter()
+                    .enumerate()
+                    .map(move |(note_index, note)| HitTime {
+                        seconds: timing.tick_to_seconds(note.time),
+                        tick: note.time,
+                        note: NoteEntity {
+                            line_index,
+                            note_index,
+                        },
+                    })
+            })
+            .collect();
+        hits.sort_by(|a, b| a.seconds.total_cmp(&b.seconds));
+        Self { hits }
+    }
+
+    /// Hits whose effect is alive at `time`
+    pub fn alive_at(&self, time: f64) -> &[HitTime] {
+        let start = self
+            .hits
+            .partition_point(|hit| hit.seconds <= time - HIT_EFFECT_DURATION);
+        let end = self.hits.partition_point(|hit| hit.seconds <= time);
+        &self.hits[start..end.max(start)]
+    }
+}
+
+/// Hits made by the player whose effect is alive at `time`
+fn judged_hits_alive_at(game_state: &GameState, judge: &Judge, time: f64) -> Vec<HitTime> {
+    judge
+        .hits()
+        .iter()
+        .filter(|hit| hit.time <= time && time - hit.time < HIT_EFFECT_DURATION)
+        .map(|hit| HitTime {
+            seconds: hit.time,
+            tick: game_state.timing.seconds_to_tick(hit.time),
+            note: NoteEntity {
+                line_index: hit.line_index,
+                note_index: hit.note_index,
+            },
+        })
+        .collect()
+}
+
+/// Pseudo-random numbers seeded by a note, so its effect looks the same however it is reached
+struct NoteRng(u64);
+
+impl NoteRng {
+    fn new(note: NoteEntity) -> Self {
+        Self(((note.line_index as u64) << 32) ^ note.note_index as u64)
+    }
+
+    /// splitmix64
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Integer in `low..=high`
+    fn range(&mut self, low: u32, high: u32) -> u32 {
+        low + (self.next_u64() % (high - low + 1) as u64) as u32
+    }
+}
+
+/// Rebuild the hit timeline of a newly inserted chart
+pub fn build_hit_timeline(game_state: Res<GameState>, mut timeline: ResMut<HitTimeline>) {
+    *timeline = HitTimeline::new(&game_state);
+}
+
+/// Spawn the effects of hits within the last `HIT_EFFECT_DURATION` seconds,
+/// despawn the others and move every piece to the current phase
+#[allow(clippy::too_many_arguments)]
+pub fn update_hit_effects(
+    mut commands: Commands,
+    game_state: Res<GameState>,
+    timeline: Res<HitTimeline>,
+    judge: Option<Res<Judge>>,
+    handles: Res<RenderHandles>,
+    playfield: Res<Playfield>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    effects: Query<(Entity, &HitEffect, &NoteEntity, &Children)>,
+    mut pieces: Query<(
+        &EffectPiece,
+        &mut Transform,
+        &mut Visibility,
+        &Handle<ColorMaterial>,
+    )>,
+) {
+    let time = game_state.current_time;
+    let scale = game_state.camera_scale() as f32;
+    let alive = match judge.filter(|_| game_state.play_mode == PlayMode::Play) {
+        Some(judge) => judged_hits_alive_at(&game_state, &judge, time),
+        None => timeline.alive_at(time).to_vec(),
+    };
+
+    let mut shown = HashSet::with_capacity(alive.len());
+    for (entity, effect, note, children) in effects.iter() {
+        let phase = effect.phase(time);
+        if !(0.0..1.0).contains(&phase) {
+            commands.entity(entity).despawn_recursive();
+            continue;
+        }
+        shown.insert(*note);
+
+        for &child in children.iter() {
+            if let Ok((piece, mut transform, mut visibility, material)) = pieces.get_mut(child) {
+                let quad = piece.quad(phase as f32, scale, effect.color);
+                place_quad(
+                    Some(quad),
+                    &mut transform,
+                    &mut visibility,
+                    material,
+                    &mut materials,
+                );
+            }
+        }
+    }
+
+    for hit in alive.iter().filter(|hit| !shown.contains(&hit.note)) {
+        let Some(line) = game_state.chart.lines.get(hit.note.line_index) else {
+            continue;
+        };
+        let Some(x) = game_state.judge_ring_x_at(line, hit.tick) else {
+            continue;
+        };
+        let effect = HitEffect {
+            start_time: hit.seconds,
+            x: x as f32 * playfield.width,
+            color: game_state.theme_color_at(hit.tick, 2),
+        };
+        spawn_hit_effect(
+            &mut commands,
+            &handles,
+            &mut materials,
+            effect,
+            hit.note,
+            time,
+            scale,
+        );
+    }
+}
+
+/// Spawn an effect with its pieces already placed at `time`,
+/// so an effect appears at the right phase on its first frame
+fn spawn_hit_effect(
+    commands: &mut Commands,
+    handles: &RenderHandles,
+    materials: &mut Assets<ColorMaterial>,
+    effect: HitEffect,
+    note: NoteEntity,
+    time: f64,
+    scale: f32,
+) {
+    let phase = effect.phase(time) as f32;
+    let mut rng = NoteRng::new(note);
+    let block_count = rng.range(3, 4);
+    let blocks = (0..block_count).map(|_| EffectPiece::Block {
+        angle: (rng.range(0, 360) as f32).to_radians(),
+        size: rng.range(10, 30) as f32,
+    });
+    let ring_material = materials.add(effect.color.to_bevy_color());
+
+    let pieces: Vec<_> = RingSide::ALL
+        .into_iter()
+        .map(EffectPiece::Ring)
+        .chain(blocks)
+        .map(|piece| {
+            let quad = piece.quad(phase, scale, effect.color);
+            let material = match piece {
+                EffectPiece::Ring(_) => ring_material.clone(),
+                EffectPiece::Block { .. } => materials.add(quad.color),
+            };
+            let mut bundle = quad_bundle(handles, material);
+            bundle.transform = Transform::from_translation(quad.center.extend(quad.z))
+                .with_scale(quad.size.extend(1.0));
+            bundle.visibility = Visibility::Inherited;
+            (bundle, piece)
+        })
+        .collect();
+
+    commands
+        .spawn((
+            SpatialBundle::from_transform(Transform::from_xyz(effect.x, 0.0, HIT_EFFECT_Z)),
+            effect,
+            note,
+            GameEntity,
+        ))
+        .with_children(|parent| {
+            for piece in pieces {
+                parent.spawn(piece);
+            }
+        });
+}
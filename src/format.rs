@@ -0,0 +1,392 @@
+//! Chart file versions.
+//!
+//! Each `fileVersion` is read with the file layout it was written in and then
+//! migrated to the in-memory `Chart`. Fields that older charts may lack (`songsName`,
+//! `lineColor`, `otherInformations`, ...) are optional in the layouts and get
+//! explicit defaults during migration, so such charts load the same way every
+//! time. Charts without a `fileVersion` predate it and are read in the layout
+//! js/formatOld.js plays. Versions without a layout are reported instead of
+//! read as another.
+//!
+//! Fields a layout does not know are kept in the model's `extra` maps, so a
+//! chart saved with `Chart::to_json_pretty` loses nothing. The `JsonShape` of
+//! the file puts the keys back in their order and writes whole numbers the way
+//! the file did.
+
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::chart::{Chart, ChartLoadError};
+
+/// `fileVersion`s this player can read
+pub const SUPPORTED_FILE_VERSIONS: [u32; 2] = [0, 1];
+
+/// Just the version of a chart file, read before the rest
+#[derive(Deserialize)]
+struct VersionProbe {
+    #[serde(rename = "fileVersion")]
+    file_version: Option<u32>,
+}
+
+/// Parse a chart file of any supported version into the current model
+pub fn parse(json: &str) -> Result<Chart, ChartLoadError> {
+    let value: Value = serde_json::from_str(json)?;
+    let probe = VersionProbe::deserialize(&value)?;
+    let shape = JsonShape::of(&value);
+    let mut chart = match probe.file_version {
+        None => serde_json::from_value::<legacy::ChartFile>(value)?.migrate(),
+        // Version 1 is still being adapted, as formatCheck.js says. Nothing
+        // the player reads is known to differ from version 0, so it is read
+        // in that layout until something does.
+        Some(0 | 1) => serde_json::from_value::<v0::ChartFile>(value)?.migrate(),
+        Some(version) => return Err(ChartLoadError::UnsupportedVersion(version)),
+    };
+    chart.shape = shape;
+    Ok(chart)
+}
+
+/// Key order and whole numbers of a chart file, which the model's `f64`s and
+/// field order do not keep
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum JsonShape {
+    /// Anything else, written as the model has it
+    #[default]
+    Other,
+    /// A number written without a fraction or exponent, such as `120`
+    Integer,
+    Array(Vec<JsonShape>),
+    /// Keys in file order
+    Object(Vec<(String, JsonShape)>),
+}
+
+impl JsonShape {
+    pub fn of(value: &Value) -> Self {
+        match value {
+            Value::Number(number) if !number.is_f64() => JsonShape::Integer,
+            Value::Array(items) => JsonShape::Array(items.iter().map(JsonShape::of).collect()),
+            Value::Object(map) => JsonShape::Object(
+                map.iter()
+                    .map(|(key, value)| (key.clone(), JsonShape::of(value)))
+                    .collect(),
+            ),
+            _ => JsonShape::Other,
+        }
+    }
+
+    /// Give `value` this shape where it still fits: keys the file had come
+    /// first in its order, new keys after them, and whole numbers that were
+    /// integers are written as integers again
+    pub fn restore(&self, value: &mut Value) {
+        match (self, value) {
+            (JsonShape::Integer, Value::Number(number)) => {
+                if let Some(x) = number
+                    .as_f64()
+                    .filter(|x| x.fract() == 0.0 && x.abs() < 2f64.powi(53))
+                {
+                    *number = (x as i64).into();
+                }
+            }
+            (JsonShape::Array(shapes), Value::Array(items)) => {
+                for (shape, item) in shapes.iter().zip(items) {
+                    shape.restore(item);
+                }
+            }
+            (JsonShape::Object(shapes), Value::Object(map)) => {
+                let mut rest = std::mem::take(map);
+                for (key, shape) in shapes {
+                    if let Some(mut item) = rest.shift_remove(key) {
+                        shape.restore(&mut item);
+                        map.insert(key.clone(), item);
+                    }
+                }
+                map.extend(rest);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Layout of charts from before `fileVersion`, the ones js/formatOld.js plays.
+/// Besides what version 0 may lack, they have no `offset`, challenges have no
+/// `checkPoint` or `transTime`, and lines may have no `notes`.
+mod legacy {
+    use serde::Deserialize;
+    use serde_json::{Map, Value};
+
+    use super::v0::NoteFile;
+    use crate::chart::{
+        BpmShift, CameraMove, CanvasMove, ChallengeTime, Chart, JudgeRingColor, Line, LineColor,
+        LinePoint, Theme,
+    };
+
+    #[derive(Deserialize)]
+    pub struct ChartFile {
+        #[serde(rename = "songsName")]
+        songs_name: Option<String>,
+        themes: Vec<Theme>,
+        #[serde(rename = "challengeTimes")]
+        challenge_times: Option<Vec<ChallengeTimeFile>>,
+        #[serde(rename = "bPM")]
+        bpm: f64,
+        #[serde(rename = "bpmShifts", default)]
+        bpm_shifts: Vec<BpmShift>,
+        offset: Option<f64>,
+        #[serde(default)]
+        lines: Vec<LineFile>,
+        #[serde(rename = "canvasMoves", default)]
+        canvas_moves: Vec<CanvasMove>,
+        #[serde(rename = "cameraMove", default)]
+        camera_move: CameraMove,
+        #[serde(flatten)]
+        extra: Map<String, Value>,
+    }
+
+    #[derive(Deserialize)]
+    struct ChallengeTimeFile {
+        #[serde(rename = "checkPoint")]
+        check_point: Option<f64>,
+        start: f64,
+        end: f64,
+        #[serde(rename = "transTime")]
+        trans_time: Option<f64>,
+        #[serde(flatten)]
+        extra: Map<String, Value>,
+    }
+
+    #[derive(Deserialize)]
+    struct LineFile {
+        #[serde(rename = "linePoints")]
+        line_points: Vec<LinePoint>,
+        notes: Option<Vec<NoteFile>>,
+        #[serde(rename = "judgeRingColor")]
+        judge_ring_color: Option<Vec<JudgeRingColor>>,
+        #[serde(rename = "lineColor")]
+        line_color: Option<Vec<LineColor>>,
+        #[serde(flatten)]
+        extra: Map<String, Value>,
+    }
+
+    impl ChartFile {
+        /// Migrated to version 0: no offset, and challenges switch theme at
+        /// once at their start, as formatOld.js draws them
+        pub fn migrate(self) -> Chart {
+            Chart {
+                file_version: 0,
+                songs_name: self.songs_name.unwrap_or_default(),
+                themes: self.themes,
+                challenge_times: self
+                    .challenge_times
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(ChallengeTimeFile::migrate)
+                    .collect(),
+                bpm: self.bpm,
+                bpm_shifts: self.bpm_shifts,
+                offset: self.offset.unwrap_or_default(),
+                lines: self.lines.into_iter().map(LineFile::migrate).collect(),
+                canvas_moves: self.canvas_moves,
+                camera_move: self.camera_move,
+                extra: self.extra,
+                shape: Default::default(),
+            }
+        }
+    }
+
+    impl ChallengeTimeFile {
+        fn migrate(self) -> ChallengeTime {
+            ChallengeTime {
+                check_point: self.check_point.unwrap_or_default(),
+                start: self.start,
+                end: self.end,
+                trans_time: self.trans_time.unwrap_or_default(),
+                extra: self.extra,
+            }
+        }
+    }
+
+    impl LineFile {
+        fn migrate(self) -> Line {
+            Line {
+                line_points: self.line_points,
+                notes: self
+                    .notes
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(NoteFile::migrate)
+                    .collect(),
+                judge_ring_color: self.judge_ring_color.unwrap_or_default(),
+                line_color: self.line_color.unwrap_or_default(),
+                extra: self.extra,
+            }
+        }
+    }
+}
+
+/// Layout of `fileVersion` 0, the charts js/format.js plays, and of version 1
+mod v0 {
+    use serde::Deserialize;
+    use serde_json::{Map, Value};
+
+    use crate::chart::{
+        BpmShift, CameraMove, CanvasMove, ChallengeTime, Chart, JudgeRingColor, Line, LineColor,
+        LinePoint, Note, Theme,
+    };
+
+    #[derive(Deserialize)]
+    pub struct ChartFile {
+        #[serde(rename = "fileVersion")]
+        file_version: u32,
+        #[serde(rename = "songsName")]
+        songs_name: Option<String>,
+        themes: Vec<Theme>,
+        #[serde(rename = "challengeTimes")]
+        challenge_times: Option<Vec<ChallengeTime>>,
+        #[serde(rename = "bPM")]
+        bpm: f64,
+        #[serde(rename = "bpmShifts", default)]
+        bpm_shifts: Vec<BpmShift>,
+        offset: f64,
+        #[serde(default)]
+        lines: Vec<LineFile>,
+        #[serde(rename = "canvasMoves", default)]
+        canvas_moves: Vec<CanvasMove>,
+        #[serde(rename = "cameraMove", default)]
+        camera_move: CameraMove,
+        #[serde(flatten)]
+        extra: Map<String, Value>,
+    }
+
+    #[derive(Deserialize)]
+    struct LineFile {
+        #[serde(rename = "linePoints")]
+        line_points: Vec<LinePoint>,
+        notes: Vec<NoteFile>,
+        #[serde(rename = "judgeRingColor")]
+        judge_ring_color: Option<Vec<JudgeRingColor>>,
+        #[serde(rename = "lineColor")]
+        line_color: Option<Vec<LineColor>>,
+        #[serde(flatten)]
+        extra: Map<String, Value>,
+    }
+
+    /// Also the note layout of charts from before `fileVersion`
+    #[derive(Deserialize)]
+    pub(super) struct NoteFile {
+        #[serde(rename = "type")]
+        note_type: u8,
+        time: f64,
+        #[serde(rename = "floorPosition")]
+        floor_position: f64,
+        #[serde(rename = "otherInformations")]
+        other_informations: Option<Vec<f64>>,
+        #[serde(flatten)]
+        extra: Map<String, Value>,
+    }
+
+    impl ChartFile {
+        /// Missing lists become empty: no challenges, no color events, and
+        /// notes without extra data. Missing lines, canvases, BPM shifts and
+        /// camera keypoints become empty too, for validation to report with
+        /// their path, as it does a hold without an end time.
+        pub fn migrate(self) -> Chart {
+            Chart {
+                file_version: self.file_version,
+                songs_name: self.songs_name.unwrap_or_default(),
+                themes: self.themes,
+                challenge_times: self.challenge_times.unwrap_or_default(),
+                bpm: self.bpm,
+                bpm_shifts: self.bpm_shifts,
+                offset: self.offset,
+                lines: self.lines.into_iter().map(LineFile::migrate).collect(),
+                canvas_moves: self.canvas_moves,
+                camera_move: self.camera_move,
+                extra: self.extra,
+                shape: Default::default(),
+            }
+        }
+    }
+
+    impl LineFile {
+        fn migrate(self) -> Line {
+            Line {
+                line_points: self.line_points,
+                notes: self.notes.into_iter().map(NoteFile::migrate).collect(),
+                judge_ring_color: self.judge_ring_color.unwrap_or_default(),
+                line_color: self.line_color.unwrap_or_default(),
+                extra: self.extra,
+            }
+        }
+    }
+
+    impl NoteFile {
+        pub(super) fn migrate(self) -> Note {
+            Note {
+                note_type: self.note_type,
+                time: self.time,
+                floor_position: self.floor_position,
+                other_informations: self.other_informations.unwrap_or_default(),
+                extra: self.extra,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::chart::tests::test_chart_json;
+    use crate::chart::{Chart, ChartLoadError};
+
+    #[test]
+    fn legacy_chart_without_file_version_loads_as_version_0() {
+        let mut file = test_chart_json(json!([]));
+        let fields = file.as_object_mut().unwrap();
+        fields.shift_remove("fileVersion");
+        fields.shift_remove("offset");
+        fields["lines"][0]
+            .as_object_mut()
+            .unwrap()
+            .shift_remove("notes");
+        fields["challengeTimes"] = json!([{ "start": 4.0, "end": 8.0 }]);
+
+        let chart = Chart::from_json(&file.to_string()).unwrap();
+        assert_eq!(chart.file_version, 0);
+        assert_eq!(chart.offset, 0.0);
+        assert!(chart.lines[0].notes.is_empty());
+        assert_eq!(chart.challenge_times[0].trans_time, 0.0);
+    }
+
+    #[test]
+    fn version_0_and_1_load() {
+        let mut file = test_chart_json(json!([]));
+        for version in [0, 1] {
+            file["fileVersion"] = json!(version);
+            let chart = Chart::from_json(&file.to_string()).unwrap();
+            assert_eq!(chart.file_version, version);
+            assert_eq!(chart.lines.len(), 1);
+        }
+    }
+
+    #[test]
+    fn version_0_requires_its_fields() {
+        let mut file = test_chart_json(json!([]));
+        file.as_object_mut().unwrap().shift_remove("offset");
+        let error = Chart::from_json(&file.to_string()).unwrap_err();
+        assert!(
+            matches!(&error, ChartLoadError::Json { message, .. } if message.contains("offset")),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn unsupported_version_is_reported() {
+        let mut file = test_chart_json(json!([]));
+        file["fileVersion"] = json!(7);
+        assert!(matches!(
+            Chart::from_json(&file.to_string()),
+            Err(ChartLoadError::UnsupportedVersion(7))
+        ));
+    }
+}
@@ -1,16 +1,38 @@
-use bevy::prelude::*;
+#[cfg(feature = "player")]
+use bevy::prelude::Resource;
 
-use crate::chart::{Chart, Color as ChartColor, JudgeRingColor, LineColor};
-use crate::timing::{find_value, recalculate_fps, seconds_to_tick, speed_to_fp, tick_to_seconds};
+use crate::chart::{Chart, Color as ChartColor, JudgeRingColor, Line, LineColor, LinePoint};
+use crate::judge::{NoteResult, PlayMode};
+use crate::timing::{find_value, FloorPositionMap, TimingMap};
+
+/// Default zoom of the revelation view
+pub const DEFAULT_REVELATION_ZOOM: f64 = 0.5;
+
+/// Revelation zoom limits; 1.0 itself would mean "off"
+pub const MIN_REVELATION_ZOOM: f64 = 0.05;
+pub const MAX_REVELATION_ZOOM: f64 = 0.95;
 
 /// Game state resource
-#[derive(Resource)]
+#[cfg_attr(feature = "player", derive(Resource))]
 pub struct GameState {
     pub chart: Chart,
+    /// Tick/seconds conversion of `chart`
+    pub timing: TimingMap,
     pub current_time: f64,
     pub is_playing: bool,
     pub speed: f64,
+    /// Song playback rate (1.0 = normal)
+    pub playback_rate: f64,
+    pub music_volume: f32,
+    /// Hit sound volume
+    pub effect_volume: f32,
+    /// Camera zoom factor, 1.0 unless the revelation view is active
     pub revelation_size: f64,
+    /// Zoom applied when the revelation view is turned on
+    pub revelation_zoom: f64,
+    pub play_mode: PlayMode,
+    /// Set by `seek` until the judge has restarted at the new time
+    pub seeked: bool,
     pub canvas_states: Vec<CanvasState>,
 }
 
@@ -24,47 +46,157 @@ impl GameState {
         }
 
         Self {
+            timing: TimingMap::from_chart(&chart),
             chart,
             current_time: 0.0,
             is_playing: false,
             speed: (215.0 / 32.0 + 10.0) * (10.0 / 129.0),
+            playback_rate: 1.0,
+            music_volume: 1.0,
+            effect_volume: 1.0,
             revelation_size: 1.0,
+            revelation_zoom: DEFAULT_REVELATION_ZOOM,
+            play_mode: PlayMode::default(),
+            seeked: false,
             canvas_states,
         }
     }
 
+    /// Whether the zoomed-out revelation view is active
+    pub fn is_revelation(&self) -> bool {
+        self.revelation_size != 1.0
+    }
+
+    /// Turn the revelation view on or off
+    pub fn toggle_revelation(&mut self) {
+        self.revelation_size = if self.is_revelation() {
+            1.0
+        } else {
+            self.revelation_zoom
+        };
+    }
+
+    /// Multiply the revelation zoom by `factor`, applying it right away when active
+    pub fn adjust_revelation_zoom(&mut self, factor: f64) {
+        self.revelation_zoom =
+            (self.revelation_zoom * factor).clamp(MIN_REVELATION_ZOOM, MAX_REVELATION_ZOOM);
+        if self.is_revelation() {
+            self.revelation_size = self.revelation_zoom;
+        }
+    }
+
+    /// Jump to `time` seconds; notes skipped over or replayed are judged afresh
+    pub fn seek(&mut self, time: f64) {
+        self.current_time = time;
+        self.seeked = true;
+    }
+
     pub fn current_tick(&self) -> f64 {
-        seconds_to_tick(self.current_time, &self.chart.bpm_shifts, self.chart.bpm)
+        self.timing.seconds_to_tick(self.current_time)
+    }
+
+    /// Floor position of canvas `canvas_index` at `seconds`
+    pub fn canvas_fp(&self, canvas_index: usize, seconds: f64) -> f64 {
+        self.canvas_states
+            .get(canvas_index)
+            .map_or(0.0, |state| state.floor_positions.fp_at(seconds))
     }
 
     pub fn camera_scale(&self) -> f64 {
-        let tick = self.current_tick();
+        self.camera_scale_at(self.current_tick())
+    }
+
+    pub fn camera_scale_at(&self, tick: f64) -> f64 {
         find_value(tick, &self.chart.camera_move.scale_key_points) * self.revelation_size
     }
 
     pub fn camera_move_x(&self) -> f64 {
-        let tick = self.current_tick();
-        find_value(tick, &self.chart.camera_move.x_position_key_points)
+        self.camera_move_x_at(self.current_tick())
     }
 
-    pub fn get_challenge_time_index(&self) -> Option<usize> {
-        let tick = self.current_tick();
-        for (i, ct) in self.chart.challenge_times.iter().enumerate() {
-            if tick >= ct.start && tick <= ct.end {
-                return Some(i + 1);
-            }
-        }
-        None
+    pub fn camera_move_x_at(&self, tick: f64) -> f64 {
+        find_value(tick, &self.chart.camera_move.x_position_key_points)
     }
 
     pub fn get_theme_color(&self, index: usize) -> ChartColor {
-        let theme_idx = self.get_challenge_time_index().unwrap_or(0);
-        if theme_idx < self.chart.themes.len() {
-            if index < self.chart.themes[theme_idx].colors_list.len() {
-                return self.chart.themes[theme_idx].colors_list[index];
+        self.theme_color_at(self.current_tick(), index)
+    }
+
+    /// Challenge theme shown at `tick` and how far it has faded in.
+    /// The challenge theme fades in over the `trans_time` ticks after `start`
+    /// and back out over the `trans_time` ticks before `end`.
+    /// The reveal effect Rizline plays from `check_point` is a backlog item of
+    /// its own: neither JS player reads `checkPoint`, so what it draws is not known.
+    pub fn challenge_blend_at(&self, tick: f64) -> Option<(usize, f32)> {
+        self.chart
+            .challenge_times
+            .iter()
+            .enumerate()
+            .find_map(|(i, ct)| {
+                let fade_in = fade_progress(tick, ct.start, ct.trans_time);
+                let fade_out = fade_progress(tick, ct.end - ct.trans_time, ct.trans_time);
+                let weight = fade_in.min(1.0 - fade_out);
+                (weight > 0.0).then_some((i + 1, weight as f32))
+            })
+    }
+
+    /// Theme palette color at `tick`, cross-faded through challenge transitions
+    pub fn theme_color_at(&self, tick: f64, index: usize) -> ChartColor {
+        let theme_color = |theme_idx: usize| {
+            self.chart
+                .themes
+                .get(theme_idx)
+                .and_then(|theme| theme.colors_list.get(index))
+                .map(|stored| stored.color)
+        };
+        let base = theme_color(0).unwrap_or_default();
+        match self.challenge_blend_at(tick) {
+            Some((theme_idx, weight)) => {
+                theme_color(theme_idx).map_or(base, |color| base.lerp(&color, weight))
             }
+            None => base,
         }
-        ChartColor::default()
+    }
+
+    /// X of the judge ring of `line` at `tick`, in playfield widths from the center.
+    /// Depends only on the chart and `tick`, not on the current canvas states.
+    pub fn judge_ring_x_at(&self, line: &Line, tick: f64) -> Option<f64> {
+        let points = &line.line_points;
+        let index = points
+            .partition_point(|point| point.time <= tick)
+            .saturating_sub(1);
+        let point = points.get(index)?;
+        let next_point = points.get(index + 1).unwrap_or(point);
+
+        let scale = self.camera_scale_at(tick);
+        let camera_x = self.camera_move_x_at(tick);
+        let point_x = |point: &LinePoint| {
+            let canvas_move = self.chart.canvas_moves.get(point.canvas_index)?;
+            let canvas_x =
+                (find_value(tick, &canvas_move.x_position_key_points) - camera_x) * scale;
+            Some(point.x_position * scale + canvas_x)
+        };
+        let x = point_x(point)?;
+        let next_x = point_x(next_point)?;
+
+        let duration = next_point.time - point.time;
+        let progress = if duration > 0.0 {
+            (tick - point.time) / duration
+        } else {
+            0.0
+        };
+        Some(x + point.ease(progress as f32) as f64 * (next_x - x))
+    }
+}
+
+/// Progress in `[0, 1]` of a fade starting at `from` and lasting `duration` ticks
+fn fade_progress(tick: f64, from: f64, duration: f64) -> f64 {
+    if duration > 0.0 {
+        ((tick - from) / duration).clamp(0.0, 1.0)
+    } else if tick >= from {
+        1.0
+    } else {
+        0.0
     }
 }
 
@@ -73,15 +205,20 @@ impl GameState {
 pub struct CanvasState {
     pub x: f64,
     pub fp: f64,
+    /// Floor position of the canvas over time
+    pub floor_positions: FloorPositionMap,
 }
 
 /// Initialize canvas states with recalculated floor positions
 pub fn initialize_canvas_states(game_state: &mut GameState) {
-    let bpm_shifts = game_state.chart.bpm_shifts.clone();
-    let base_bpm = game_state.chart.bpm;
-
-    for canvas_move in &mut game_state.chart.canvas_moves {
-        recalculate_fps(&mut canvas_move.speed_key_points, &bpm_shifts, base_bpm);
+    for (canvas_move, state) in game_state
+        .chart
+        .canvas_moves
+        .iter()
+        .zip(&mut game_state.canvas_states)
+    {
+        state.floor_positions =
+            FloorPositionMap::new(&canvas_move.speed_key_points, &game_state.timing);
     }
 }
 
@@ -94,20 +231,33 @@ pub fn update_canvas_states(game_state: &mut GameState) {
 
     for (i, canvas_move) in game_state.chart.canvas_moves.iter().enumerate() {
         let x_value = find_value(tick, &canvas_move.x_position_key_points);
-        let fp = speed_to_fp(
-            timer,
-            &canvas_move.speed_key_points,
-            &game_state.chart.bpm_shifts,
-            game_state.chart.bpm,
-        );
-
         if i < game_state.canvas_states.len() {
+            let fp = game_state.canvas_fp(i, timer);
             game_state.canvas_states[i].x = (x_value - camera_x) * scale;
             game_state.canvas_states[i].fp = fp;
         }
     }
 }
 
+/// Time in seconds of the last note, hold ends included
+pub fn chart_end_time(chart: &Chart) -> f64 {
+    let timing = TimingMap::from_chart(chart);
+    chart
+        .lines
+        .iter()
+        .flat_map(|line| line.notes.iter())
+        .map(|note| {
+            let end_tick = note
+                .other_informations
+                .first()
+                .copied()
+                .unwrap_or(note.time)
+                .max(note.time);
+            timing.tick_to_seconds(end_tick)
+        })
+        .fold(0.0, f64::max)
+}
+
 /// Get current line color from line color events
 pub fn get_current_line_color(line_color: &[LineColor], tick: f64) -> Option<ChartColor> {
     if line_color.is_empty() {
@@ -115,11 +265,11 @@ pub fn get_current_line_color(line_color: &[LineColor], tick: f64) -> Option<Cha
     }
 
     if line_color.len() == 1 {
-        return Some(line_color[0].start_color);
+        return Some(line_color[0].start_color.color);
     }
 
     if tick >= line_color[line_color.len() - 1].time {
-        return Some(line_color[line_color.len() - 1].end_color);
+        return Some(line_color[line_color.len() - 1].end_color.color);
     }
 
     for i in 0..line_color.len() {
@@ -134,27 +284,35 @@ pub fn get_current_line_color(line_color: &[LineColor], tick: f64) -> Option<Cha
             let duration = next_time - segment.time;
             if duration > 0.0 {
                 let progress = ((tick - segment.time) / duration) as f32;
-                return Some(segment.start_color.lerp(&segment.end_color, progress));
+                return Some(
+                    segment
+                        .start_color
+                        .color
+                        .lerp(&segment.end_color.color, progress),
+                );
             }
-            return Some(segment.start_color);
+            return Some(segment.start_color.color);
         }
     }
 
-    Some(line_color[0].start_color)
+    Some(line_color[0].start_color.color)
 }
 
 /// Get current judge ring color
-pub fn get_current_judge_ring_color(judge_ring_color: &[JudgeRingColor], tick: f64) -> Option<ChartColor> {
+pub fn get_current_judge_ring_color(
+    judge_ring_color: &[JudgeRingColor],
+    tick: f64,
+) -> Option<ChartColor> {
     if judge_ring_color.is_empty() {
         return None;
     }
 
     if judge_ring_color.len() == 1 {
-        return Some(judge_ring_color[0].start_color);
+        return Some(judge_ring_color[0].start_color.color);
     }
 
     if tick >= judge_ring_color[judge_ring_color.len() - 1].time {
-        return Some(judge_ring_color[judge_ring_color.len() - 1].end_color);
+        return Some(judge_ring_color[judge_ring_color.len() - 1].end_color.color);
     }
 
     for i in 0..judge_ring_color.len() {
@@ -169,13 +327,18 @@ pub fn get_current_judge_ring_color(judge_ring_color: &[JudgeRingColor], tick: f
             let duration = next_time - segment.time;
             if duration > 0.0 {
                 let progress = ((tick - segment.time) / duration) as f32;
-                return Some(segment.start_color.lerp(&segment.end_color, progress));
+                return Some(
+                    segment
+                        .start_color
+                        .color
+                        .lerp(&segment.end_color.color, progress),
+                );
             }
-            return Some(segment.start_color);
+            return Some(segment.start_color.color);
         }
     }
 
-    Some(judge_ring_color[0].start_color)
+    Some(judge_ring_color[0].start_color.color)
 }
 
 /// Calculate mixed color from point color and line color
@@ -196,7 +359,6 @@ pub fn calculate_mixed_color(
 }
 
 /// Calculate combo score
-#[allow(dead_code)]
 pub fn calculate_combo(combo: u32) -> u32 {
     if combo == 0 {
         0
@@ -212,12 +374,10 @@ pub fn calculate_combo(combo: u32) -> u32 {
 }
 
 /// Line point with computed values for rendering
-#[allow(dead_code)]
 pub struct ComputedLinePoint {
     pub x: f64,
     pub y: f64,
     pub color: ChartColor,
-    pub ease_type: u8,
 }
 
 /// Compute line point position and color
@@ -233,40 +393,69 @@ pub fn compute_line_point(
     let tick = game_state.current_tick();
     let scale = game_state.camera_scale();
 
-    let point_fp = point.fp.unwrap_or_else(|| {
-        speed_to_fp(
-            tick_to_seconds(point.time, &game_state.chart.bpm_shifts, game_state.chart.bpm),
-            &game_state.chart.canvas_moves[point.canvas_index].speed_key_points,
-            &game_state.chart.bpm_shifts,
-            game_state.chart.bpm,
-        )
-    });
+    let point_fp = game_state.canvas_fp(
+        point.canvas_index,
+        game_state.timing.tick_to_seconds(point.time),
+    );
 
     let x = point.x_position * scale * screen_width + canvas_x;
     let y = (point_fp - canvas_fp) * screen_height * game_state.speed * scale;
 
-    let mix_color = calculate_mixed_color(tick, &point.color, line_color);
+    let mix_color = calculate_mixed_color(tick, &point.color.color, line_color);
 
     ComputedLinePoint {
         x,
         y,
         color: mix_color,
-        ease_type: point.ease_type,
     }
 }
 
 /// Note state for tracking hit status
-#[derive(Clone)]
+#[derive(Clone, Default)]
 pub struct NoteState {
     pub is_hit: bool,
     pub is_play_hit: bool,
+    /// Judgement, once the note is resolved
+    pub result: Option<NoteResult>,
 }
 
-impl Default for NoteState {
-    fn default() -> Self {
-        Self {
-            is_hit: false,
-            is_play_hit: false,
-        }
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::chart::tests::test_chart_json;
+
+    /// Chart whose challenge from tick 10 to 20 fades over 2 ticks into a black theme
+    fn challenge_state() -> GameState {
+        let mut file = test_chart_json(json!([]));
+        let black = json!({ "r": 0, "g": 0, "b": 0, "a": 255 });
+        file["themes"]
+            .as_array_mut()
+            .unwrap()
+            .push(json!({ "colorsList": [black, black, black] }));
+        file["challengeTimes"] =
+            json!([{ "checkPoint": 0.0, "start": 10.0, "end": 20.0, "transTime": 2.0 }]);
+        GameState::new(Chart::from_json(&file.to_string()).unwrap())
+    }
+
+    #[test]
+    fn challenge_fades_in_from_start_and_out_by_end() {
+        let state = challenge_state();
+        assert_eq!(state.challenge_blend_at(9.0), None);
+        assert_eq!(state.challenge_blend_at(10.0), None);
+        assert_eq!(state.challenge_blend_at(11.0), Some((1, 0.5)));
+        assert_eq!(state.challenge_blend_at(12.0), Some((1, 1.0)));
+        assert_eq!(state.challenge_blend_at(15.0), Some((1, 1.0)));
+        assert_eq!(state.challenge_blend_at(19.0), Some((1, 0.5)));
+        assert_eq!(state.challenge_blend_at(20.0), None);
+    }
+
+    #[test]
+    fn theme_colors_cross_fade() {
+        let state = challenge_state();
+        assert_eq!(state.theme_color_at(5.0, 0).r, 255);
+        assert_eq!(state.theme_color_at(11.0, 0).r, 128);
+        assert_eq!(state.theme_color_at(15.0, 0).r, 0);
     }
 }
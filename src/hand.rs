@@ -0,0 +1,218 @@
+//! Autoplay hand: a cursor gliding along the judge line to each note as it
+//! is hit, like `drawAutoHand` in the JS player. It follows the judge ring
+//! through holds and eases over to the next note in between.
+//!
+//! Like hit effects, the hand is a pure function of the current time, so
+//! seeking and offline renders place it correctly.
+
+use bevy::prelude::*;
+
+use crate::chart::NoteType;
+use crate::easing::ease_in_out_cubic;
+use crate::game::GameState;
+use crate::judge::PlayMode;
+use crate::rendering::{GameEntity, Playfield};
+
+/// Depth of the hand, above hit effects and below the revelation overlay
+const HAND_Z: f32 = 5.0;
+
+/// Longest time the hand takes to move to the next note; it waits before longer gaps
+pub const HAND_MOVE_TIME: f64 = 0.3;
+
+/// Hand settings
+#[derive(Resource, Clone)]
+pub struct HandConfig {
+    pub enabled: bool,
+    /// Image drawn as the hand; a plain round cursor when `None`
+    pub sprite: Option<Handle<Image>>,
+    /// Width of the hand in units of `width / 360`
+    pub size: f32,
+}
+
+impl Default for HandConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            sprite: None,
+            size: 40.0,
+        }
+    }
+}
+
+/// Note the hand moves to
+#[derive(Clone, Copy)]
+struct Waypoint {
+    line_index: usize,
+    seconds: f64,
+    tick: f64,
+    /// End of a hold, the note time for other notes
+    end_seconds: f64,
+    end_tick: f64,
+}
+
+/// Notes of the chart in hit order; of notes hit together, only the first is kept
+#[derive(Resource, Default)]
+pub struct HandPath {
+    waypoints: Vec<Waypoint>,
+}
+
+impl HandPath {
+    pub fn new(game_state: &GameState) -> Self {
+        let chart = &game_state.chart;
+        let seconds = |tick: f64| game_state.timing.tick_to_seconds(tick);
+
+        let mut waypoints: Vec<Waypoint> = chart
+            .lines
+            .iter()
+            .enumerate()
+            .flat_map(|(line_index, line)| {
+                line.notes.iter().map(move |note| {
+                    let end_tick = match NoteType::from(note.note_type) {
+                        NoteType::Hold => note
+                            .other_informations
+                            .first()
+                            .copied()
+                            .unwrap_or(note.time)
+                            .max(note.time),
+                        _ => note.time,
+                    };
+                    Waypoint {
+                        line_index,
+                        seconds: seconds(note.time),
+                        tick: note.time,
+                        end_seconds: seconds(end_tick),
+                        end_tick,
+                    }
+                })
+            })
+            .collect();
+        waypoints.sort_by(|a, b| a.seconds.total_cmp(&b.seconds));
+        waypoints.dedup_by(|next, first| next.seconds == first.seconds);
+        Self { waypoints }
+    }
+
+    /// X of the hand at `time`, in playfield widths from the center
+    fn x_at(&self, game_state: &GameState, time: f64) -> Option<f64> {
+        let ring_x = |waypoint: &Waypoint, tick: f64| {
+            let line = game_state.chart.lines.get(waypoint.line_index)?;
+            game_state.judge_ring_x_at(line, tick)
+        };
+
+        let next_index = self
+            .waypoints
+            .partition_point(|waypoint| waypoint.seconds <= time);
+        let Some(last) = next_index
+            .checked_sub(1)
+            .map(|index| &self.waypoints[index])
+        else {
+            // Waiting for the first note
+            let first = self.waypoints.first()?;
+            return ring_x(first, first.tick);
+        };
+
+        if time <= last.end_seconds {
+            // Holding: follow the judge ring
+            return ring_x(last, game_state.current_tick());
+        }
+        let from = ring_x(last, last.end_tick)?;
+        let Some(next) = self.waypoints.get(next_index) else {
+            return Some(from);
+        };
+        let to = ring_x(next, next.tick)?;
+
+        let move_start = last.end_seconds.max(next.seconds - HAND_MOVE_TIME);
+        let duration = next.seconds - move_start;
+        let progress = if duration > 0.0 {
+            ((time - move_start) / duration).clamp(0.0, 1.0)
+        } else {
+            1.0
+        };
+        Some(from + ease_in_out_cubic(progress as f32) as f64 * (to - from))
+    }
+}
+
+/// Root of the hand; its visuals are children
+#[derive(Component)]
+pub struct AutoHand;
+
+/// Rebuild the hand path of a newly inserted chart and spawn the hidden hand
+pub fn spawn_hand(
+    mut commands: Commands,
+    game_state: Res<GameState>,
+    config: Res<HandConfig>,
+    images: Res<Assets<Image>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    commands.insert_resource(HandPath::new(&game_state));
+    if !config.enabled {
+        return;
+    }
+
+    let mut hand = commands.spawn((
+        SpatialBundle {
+            visibility: Visibility::Hidden,
+            ..default()
+        },
+        GameEntity,
+        AutoHand,
+    ));
+    hand.with_children(|parent| match &config.sprite {
+        Some(sprite) => {
+            // `size` is the width; the height keeps the image's aspect ratio
+            let aspect = images
+                .get(sprite)
+                .map_or(1.0, |image| image.height() as f32 / image.width() as f32);
+            parent.spawn(SpriteBundle {
+                sprite: Sprite {
+                    custom_size: Some(Vec2::new(1.0, aspect)),
+                    ..default()
+                },
+                texture: sprite.clone(),
+                ..default()
+            });
+        }
+        None => {
+            // Dark rim around a light dot, visible over any theme
+            let circle = meshes.add(Circle::new(0.5));
+            for (size, z, material) in [
+                (1.0, 0.0, materials.add(Color::BLACK.with_alpha(0.6))),
+                (0.7, 0.1, materials.add(Color::WHITE.with_alpha(0.9))),
+            ] {
+                parent.spawn(ColorMesh2dBundle {
+                    mesh: circle.clone().into(),
+                    material,
+                    transform: Transform::from_xyz(0.0, 0.0, z)
+                        .with_scale(Vec3::new(size, size, 1.0)),
+                    ..default()
+                });
+            }
+        }
+    });
+}
+
+/// Move the hand along its path; shown in autoplay only
+pub fn update_hand(
+    game_state: Res<GameState>,
+    config: Res<HandConfig>,
+    path: Option<Res<HandPath>>,
+    playfield: Res<Playfield>,
+    mut hands: Query<(&mut Transform, &mut Visibility), With<AutoHand>>,
+) {
+    let x = path
+        .filter(|_| game_state.play_mode == PlayMode::Autoplay)
+        .and_then(|path| path.x_at(&game_state, game_state.current_time));
+    let size = config.size * playfield.width / 360.0 * game_state.revelation_size as f32;
+
+    for (mut transform, mut visibility) in hands.iter_mut() {
+        let Some(x) = x else {
+            visibility.set_if_neq(Visibility::Hidden);
+            continue;
+        };
+        visibility.set_if_neq(Visibility::Inherited);
+        transform.set_if_neq(
+            Transform::from_xyz(x as f32 * playfield.width, 0.0, HAND_Z)
+                .with_scale(Vec3::new(size, size, 1.0)),
+        );
+    }
+}
@@ -0,0 +1,450 @@
+//! Offline video rendering without a window.
+//!
+//! The camera renders into an image; a render graph node copies it into a
+//! mappable buffer, and the pixels are sent back to the main world where they
+//! are written as a PNG sequence or piped to ffmpeg. Pipelined rendering is
+//! disabled, so the frame of update `n` is always available at update `n + 1`.
+
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use bevy::app::AppExit;
+use bevy::prelude::*;
+use bevy::render::camera::{RenderTarget, ScalingMode};
+use bevy::render::render_asset::{RenderAssetUsages, RenderAssets};
+use bevy::render::render_graph::{
+    self, NodeRunError, RenderGraph, RenderGraphContext, RenderLabel,
+};
+use bevy::render::render_resource::{
+    Buffer, BufferDescriptor, BufferUsages, CommandEncoderDescriptor, Extent3d, ImageCopyBuffer,
+    ImageDataLayout, Maintain, MapMode, TextureDimension, TextureFormat, TextureUsages,
+};
+use bevy::render::renderer::{RenderContext, RenderDevice, RenderQueue};
+use bevy::render::texture::GpuImage;
+use bevy::render::{Extract, Render, RenderApp, RenderSet};
+
+use crate::game::GameState;
+use crate::rendering::{spawn_background, update_game_time, Playfield};
+
+/// Frames rendered at the start time and discarded while pipelines warm up
+pub const WARMUP_FRAMES: u64 = 5;
+
+/// Environment variable overriding the ffmpeg executable (same as recorder.py)
+pub const FFMPEG_PATH_ENV: &str = "FFMPEG_PATH";
+
+/// Longest wait for a frame from the render world
+const FRAME_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Pixel format of the render target; frames are tightly packed RGBA8
+const TARGET_FORMAT: TextureFormat = TextureFormat::Rgba8UnormSrgb;
+
+/// Where rendered frames go
+#[derive(Debug, Clone)]
+pub enum RenderOutput {
+    PngSequence(PathBuf),
+    Ffmpeg(PathBuf),
+}
+
+/// Destination of rendered RGBA frames
+pub enum FrameSink {
+    PngSequence { dir: PathBuf, next: u64 },
+    Ffmpeg { child: Child, stdin: ChildStdin },
+}
+
+impl FrameSink {
+    /// Create the output directory or start ffmpeg.
+    /// `audio` is muxed into the video, starting at `start` seconds.
+    pub fn open(
+        output: &RenderOutput,
+        width: u32,
+        height: u32,
+        fps: u32,
+        audio: Option<&Path>,
+        start: f64,
+    ) -> io::Result<Self> {
+        match output {
+            RenderOutput::PngSequence(dir) => {
+                fs::create_dir_all(dir)?;
+                Ok(FrameSink::PngSequence {
+                    dir: dir.clone(),
+                    next: 0,
+                })
+            }
+            RenderOutput::Ffmpeg(path) => {
+                let ffmpeg =
+                    std::env::var(FFMPEG_PATH_ENV).unwrap_or_else(|_| "ffmpeg".to_string());
+                let mut command = Command::new(ffmpeg);
+                command
+                    .args(["-y", "-loglevel", "error"])
+                    .args(["-f", "rawvideo", "-pix_fmt", "rgba"])
+                    .args(["-s", &format!("{}x{}", width, height)])
+                    .args(["-r", &fps.to_string()])
+                    .args(["-i", "pipe:0"]);
+                if let Some(audio) = audio {
+                    command
+                        .args(["-ss", &start.to_string(), "-i"])
+                        .arg(audio)
+                        .args([
+                            "-map",
+                            "0:v",
+                            "-map",
+                            "1:a",
+                            "-c:a",
+                            "aac",
+                            "-b:a",
+                            "192k",
+                            "-shortest",
+                        ]);
+                }
+                command
+                    .args(["-c:v", "libx264", "-pix_fmt", "yuv420p"])
+                    .arg(path)
+                    .stdin(Stdio::piped());
+
+                let mut child = command.spawn()?;
+                let stdin = child.stdin.take().expect("ffmpeg stdin is piped");
+                Ok(FrameSink::Ffmpeg { child, stdin })
+            }
+        }
+    }
+
+    fn write(&mut self, width: u32, height: u32, rgba: Vec<u8>) -> io::Result<()> {
+        match self {
+            FrameSink::PngSequence { dir, next } => {
+                let image = Image::new(
+                    Extent3d {
+                        width,
+                        height,
+                        depth_or_array_layers: 1,
+                    },
+                    TextureDimension::D2,
+                    rgba,
+                    TARGET_FORMAT,
+                    RenderAssetUsages::MAIN_WORLD,
+                );
+                let path = dir.join(format!("{:06}.png", next));
+                *next += 1;
+                image
+                    .try_into_dynamic()
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{:?}", e)))?
+                    .save(&path)
+                    .map_err(io::Error::other)
+            }
+            FrameSink::Ffmpeg { stdin, .. } => stdin.write_all(&rgba),
+        }
+    }
+
+    /// Flush the output; waits for ffmpeg to finish encoding
+    fn finish(self) -> io::Result<()> {
+        match self {
+            FrameSink::PngSequence { .. } => Ok(()),
+            FrameSink::Ffmpeg { mut child, stdin } => {
+                drop(stdin);
+                let status = child.wait()?;
+                if status.success() {
+                    Ok(())
+                } else {
+                    Err(io::Error::other(format!("ffmpeg exited with {}", status)))
+                }
+            }
+        }
+    }
+}
+
+/// Offline render progress
+#[derive(Resource)]
+pub struct RenderJob {
+    pub width: u32,
+    pub height: u32,
+    pub fps: u32,
+    pub start: f64,
+    pub total_frames: u64,
+    /// App updates started so far
+    updates: u64,
+    /// Frames handed to the sink
+    written: u64,
+    sink: Option<FrameSink>,
+}
+
+impl RenderJob {
+    pub fn new(
+        width: u32,
+        height: u32,
+        fps: u32,
+        start: f64,
+        duration: f64,
+        sink: FrameSink,
+    ) -> Self {
+        Self {
+            width,
+            height,
+            fps,
+            start,
+            total_frames: (duration * fps as f64).ceil().max(1.0) as u64,
+            updates: 0,
+            written: 0,
+            sink: Some(sink),
+        }
+    }
+
+    /// Chart time of the frame rendered by the current update
+    fn current_time(&self) -> f64 {
+        let frame = self.updates.saturating_sub(1).saturating_sub(WARMUP_FRAMES);
+        self.start + frame as f64 / self.fps as f64
+    }
+}
+
+/// Main world end of the frame channel
+#[derive(Resource)]
+struct FrameReceiver(Mutex<Receiver<Vec<u8>>>);
+
+/// Render world end of the frame channel
+#[derive(Resource)]
+struct FrameSender(Sender<Vec<u8>>);
+
+/// Plugin rendering the chart offscreen according to the `RenderJob` resource
+pub struct HeadlessRenderPlugin;
+
+impl Plugin for HeadlessRenderPlugin {
+    fn build(&self, app: &mut App) {
+        let (sender, receiver) = mpsc::channel();
+
+        app.insert_resource(FrameReceiver(Mutex::new(receiver)))
+            .add_systems(Startup, setup_render_target)
+            .add_systems(PreUpdate, capture_frame)
+            .add_systems(Update, step_render_clock.before(update_game_time));
+
+        let render_app = app.sub_app_mut(RenderApp);
+        let mut graph = render_app.world_mut().resource_mut::<RenderGraph>();
+        graph.add_node(ImageCopy, ImageCopyDriver);
+        graph.add_node_edge(bevy::render::graph::CameraDriverLabel, ImageCopy);
+
+        render_app
+            .insert_resource(FrameSender(sender))
+            .add_systems(ExtractSchedule, extract_image_copier)
+            .add_systems(Render, send_frame.after(RenderSet::Render));
+    }
+}
+
+/// Copies the render target into `buffer` every frame
+#[derive(Component, Resource, Clone)]
+struct ImageCopier {
+    buffer: Buffer,
+    src_image: Handle<Image>,
+}
+
+fn setup_render_target(
+    mut commands: Commands,
+    mut images: ResMut<Assets<Image>>,
+    render_device: Res<RenderDevice>,
+    playfield: Res<Playfield>,
+    job: Res<RenderJob>,
+) {
+    let size = Extent3d {
+        width: job.width,
+        height: job.height,
+        ..default()
+    };
+
+    let mut target = Image::new_fill(
+        size,
+        TextureDimension::D2,
+        &[0; 4],
+        TARGET_FORMAT,
+        RenderAssetUsages::default(),
+    );
+    target.texture_descriptor.usage |=
+        TextureUsages::COPY_SRC | TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING;
+    let target = images.add(target);
+
+    let padded_bytes_per_row = RenderDevice::align_copy_bytes_per_row(job.width as usize * 4);
+    let buffer = render_device.create_buffer(&BufferDescriptor {
+        label: Some("headless_frame_buffer"),
+        size: padded_bytes_per_row as u64 * job.height as u64,
+        usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+    commands.spawn(ImageCopier {
+        buffer,
+        src_image: target.clone(),
+    });
+
+    // Map the logical playfield onto the output resolution
+    let mut camera = Camera2dBundle {
+        camera: Camera {
+            target: RenderTarget::Image(target),
+            ..default()
+        },
+        transform: Transform::from_xyz(0.0, playfield.camera_offset(), 0.0),
+        ..default()
+    };
+    camera.projection.scaling_mode = ScalingMode::Fixed {
+        width: playfield.width,
+        height: playfield.height,
+    };
+    commands.spawn(camera);
+
+    spawn_background(&mut commands, &playfield);
+}
+
+/// Drive the game clock at a fixed frame rate
+fn step_render_clock(job: Res<RenderJob>, mut game_state: ResMut<GameState>) {
+    game_state.is_playing = false;
+    game_state.current_time = job.current_time();
+}
+
+/// Hand the previous update's frame to the sink and stop when done
+fn capture_frame(
+    mut job: ResMut<RenderJob>,
+    receiver: Res<FrameReceiver>,
+    mut app_exit: EventWriter<AppExit>,
+) {
+    if job.updates > 0 {
+        let frame = receiver.0.lock().unwrap().recv_timeout(FRAME_TIMEOUT);
+        let Ok(data) = frame else {
+            eprintln!("Render error: no frame received from the renderer");
+            app_exit.send(AppExit::error());
+            return;
+        };
+
+        if job.updates > WARMUP_FRAMES {
+            let (width, height) = (job.width, job.height);
+            let rgba = strip_row_padding(data, width, height);
+            let written = job
+                .sink
+                .as_mut()
+                .map(|sink| sink.write(width, height, rgba));
+            if let Some(Err(e)) = written {
+                eprintln!("Failed to write frame: {}", e);
+                app_exit.send(AppExit::error());
+                return;
+            }
+
+            job.written += 1;
+            if job.written.is_multiple_of(job.fps as u64) || job.written == job.total_frames {
+                println!("Rendered {}/{} frames", job.written, job.total_frames);
+            }
+        }
+    }
+
+    if job.written >= job.total_frames {
+        if let Some(sink) = job.sink.take() {
+            match sink.finish() {
+                Ok(()) => {
+                    println!("Render finished");
+                    app_exit.send(AppExit::Success);
+                }
+                Err(e) => {
+                    eprintln!("Failed to finish output: {}", e);
+                    app_exit.send(AppExit::error());
+                }
+            }
+        }
+        return;
+    }
+
+    job.updates += 1;
+}
+
+/// Remove the row alignment padding required by buffer copies
+fn strip_row_padding(data: Vec<u8>, width: u32, height: u32) -> Vec<u8> {
+    let row_bytes = width as usize * 4;
+    let padded_row_bytes = RenderDevice::align_copy_bytes_per_row(row_bytes);
+    if row_bytes == padded_row_bytes {
+        return data;
+    }
+    data.chunks(padded_row_bytes)
+        .take(height as usize)
+        .flat_map(|row| &row[..row_bytes])
+        .copied()
+        .collect()
+}
+
+fn extract_image_copier(mut commands: Commands, copiers: Extract<Query<&ImageCopier>>) {
+    if let Some(copier) = copiers.iter().next() {
+        commands.insert_resource(copier.clone());
+    }
+}
+
+/// Render graph label of `ImageCopyDriver`
+#[derive(Debug, PartialEq, Eq, Clone, Hash, RenderLabel)]
+struct ImageCopy;
+
+/// Render graph node copying the render target into the copier buffer
+#[derive(Default)]
+struct ImageCopyDriver;
+
+impl render_graph::Node for ImageCopyDriver {
+    fn run(
+        &self,
+        _graph: &mut RenderGraphContext,
+        render_context: &mut RenderContext,
+        world: &World,
+    ) -> Result<(), NodeRunError> {
+        let Some(copier) = world.get_resource::<ImageCopier>() else {
+            return Ok(());
+        };
+        let gpu_images = world.resource::<RenderAssets<GpuImage>>();
+        let Some(src_image) = gpu_images.get(&copier.src_image) else {
+            return Ok(());
+        };
+
+        let mut encoder = render_context
+            .render_device()
+            .create_command_encoder(&CommandEncoderDescriptor::default());
+
+        let padded_bytes_per_row =
+            RenderDevice::align_copy_bytes_per_row(src_image.size.x as usize * 4);
+        encoder.copy_texture_to_buffer(
+            src_image.texture.as_image_copy(),
+            ImageCopyBuffer {
+                buffer: &copier.buffer,
+                layout: ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(padded_bytes_per_row as u32),
+                    rows_per_image: None,
+                },
+            },
+            Extent3d {
+                width: src_image.size.x,
+                height: src_image.size.y,
+                depth_or_array_layers: 1,
+            },
+        );
+
+        world
+            .resource::<RenderQueue>()
+            .submit(std::iter::once(encoder.finish()));
+
+        Ok(())
+    }
+}
+
+/// Read back the copier buffer after rendering and send it to the main world
+fn send_frame(
+    copier: Option<Res<ImageCopier>>,
+    render_device: Res<RenderDevice>,
+    sender: Res<FrameSender>,
+) {
+    let Some(copier) = copier else {
+        return;
+    };
+
+    let slice = copier.buffer.slice(..);
+    let (mapped_sender, mapped) = mpsc::sync_channel(1);
+    slice.map_async(MapMode::Read, move |result| {
+        let _ = mapped_sender.send(result);
+    });
+    render_device.poll(Maintain::wait()).panic_on_timeout();
+
+    if let Ok(Ok(())) = mapped.recv() {
+        // The main world may already be gone when the last frame arrives
+        let _ = sender.0.send(slice.get_mapped_range().to_vec());
+        copier.buffer.unmap();
+    }
+}
@@ -0,0 +1,132 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::audio::{AudioBuffer, AudioError};
+use crate::chart::{Chart, NoteType};
+use crate::timing::TimingMap;
+
+/// Directory holding the hit sounds, in the repository and next to a release build
+pub const HIT_SOUND_DIR: &str = "audio";
+/// Hit sound for Tap and Hold notes
+pub const HIT_SOUND_FILE: &str = "hit.wav";
+/// Hit sound for Drag notes
+pub const DRAG_SOUND_FILE: &str = "drag.wav";
+
+/// Hit sound directory used when none is given: `audio` in the working
+/// directory, next to the executable, or in the source tree it was built from
+pub fn find_hit_sound_dir() -> Option<PathBuf> {
+    let exe_dir = std::env::current_exe()
+        .ok()
+        .and_then(|exe| exe.parent().map(Path::to_path_buf));
+    [
+        Some(PathBuf::from(HIT_SOUND_DIR)),
+        exe_dir.map(|dir| dir.join(HIT_SOUND_DIR)),
+        Some(Path::new(env!("CARGO_MANIFEST_DIR")).join(HIT_SOUND_DIR)),
+    ]
+    .into_iter()
+    .flatten()
+    .find(|dir| dir.join(HIT_SOUND_FILE).is_file())
+}
+
+/// Hit sounds that could not be loaded
+#[derive(Debug)]
+pub enum HitSoundError {
+    /// No directory given and none of the default ones has the sounds
+    NotFound,
+    /// A sound file is missing or cannot be decoded
+    Audio(PathBuf, AudioError),
+}
+
+impl fmt::Display for HitSoundError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HitSoundError::NotFound => write!(
+                f,
+                "no {}/{} in the working directory, next to the executable or in the source tree; \
+                 pass the directory with --hit-sounds",
+                HIT_SOUND_DIR, HIT_SOUND_FILE
+            ),
+            HitSoundError::Audio(path, e) => write!(f, "{}: {}", path.display(), e),
+        }
+    }
+}
+
+impl std::error::Error for HitSoundError {}
+
+/// Hit sound triggered at an exact sample frame
+#[derive(Debug, Clone, Copy)]
+pub struct ScheduledHit {
+    pub frame: u64,
+    pub note_type: NoteType,
+}
+
+/// Sample frame at which a sound starting at `seconds` begins
+pub fn hit_frame(seconds: f64, sample_rate: u32) -> u64 {
+    (seconds.max(0.0) * sample_rate as f64).round() as u64
+}
+
+/// Hit sounds of every note in the chart, sorted by frame
+pub fn schedule_hits(chart: &Chart, sample_rate: u32) -> Vec<ScheduledHit> {
+    let timing = TimingMap::from_chart(chart);
+    let mut hits: Vec<ScheduledHit> = chart
+        .lines
+        .iter()
+        .flat_map(|line| line.notes.iter())
+        .map(|note| ScheduledHit {
+            frame: hit_frame(timing.tick_to_seconds(note.time), sample_rate),
+            note_type: NoteType::from(note.note_type),
+        })
+        .collect();
+    hits.sort_by_key(|hit| hit.frame);
+    hits
+}
+
+/// Pre-decoded hit sounds, converted to the format of the song they are mixed into
+#[derive(Clone)]
+pub struct HitSounds {
+    pub hit: Arc<[f32]>,
+    pub drag: Arc<[f32]>,
+}
+
+impl HitSounds {
+    /// Load `HIT_SOUND_FILE` and `DRAG_SOUND_FILE` from `dir`, or from
+    /// `find_hit_sound_dir` without one, in the given format
+    pub fn load(
+        dir: Option<&Path>,
+        channels: u16,
+        sample_rate: u32,
+    ) -> Result<Self, HitSoundError> {
+        let dir = match dir {
+            Some(dir) => dir.to_path_buf(),
+            None => find_hit_sound_dir().ok_or(HitSoundError::NotFound)?,
+        };
+        let decode = |file: &str| {
+            let path = dir.join(file);
+            AudioBuffer::decode_as(&path, channels, sample_rate)
+                .map_err(|e| HitSoundError::Audio(path, e))
+        };
+        let hit = decode(HIT_SOUND_FILE)?;
+        let drag = decode(DRAG_SOUND_FILE)?;
+
+        Ok(Self {
+            hit: hit.samples,
+            drag: drag.samples,
+        })
+    }
+
+    /// No hit sounds at all
+    pub fn silent() -> Self {
+        Self {
+            hit: Vec::new().into(),
+            drag: Vec::new().into(),
+        }
+    }
+
+    pub fn sample_for(&self, note_type: NoteType) -> &Arc<[f32]> {
+        match note_type {
+            NoteType::Tap | NoteType::Hold => &self.hit,
+            NoteType::Drag => &self.drag,
+        }
+    }
+}
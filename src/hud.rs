@@ -0,0 +1,177 @@
+//! Combo counter, song title, elapsed time and watermark.
+//!
+//! The HUD is drawn as world-space text laid out over the visible area.
+//! Sizes follow the JS player, which scales everything by `width / 360`
+//! and `height / 640`.
+
+use bevy::prelude::*;
+use bevy::sprite::Anchor;
+use bevy::window::PrimaryWindow;
+
+use crate::game::{calculate_combo, chart_end_time, GameState};
+use crate::rendering::{GameEntity, Playfield};
+use crate::results::Score;
+
+/// Depth of the HUD, above everything else
+const HUD_Z: f32 = 10.0;
+
+/// Label drawn before the combo
+pub const COMBO_LABEL: &str = "CATPLAY ";
+
+/// HUD settings
+#[derive(Resource, Default, Clone)]
+pub struct HudConfig {
+    /// Line drawn like the JS `drawShuiYin` watermark; hidden when `None`
+    pub watermark: Option<String>,
+    /// Total time shown after the elapsed time; defaults to the end of the last note
+    pub duration: Option<f64>,
+}
+
+/// HUD text item
+#[derive(Component, Clone, Copy, PartialEq)]
+pub enum HudText {
+    /// Combo score in the top-right, reset by misses; sections are the label and the score
+    Combo,
+    Title,
+    /// Elapsed time out of `duration` seconds
+    Time {
+        duration: f64,
+    },
+    Watermark,
+}
+
+/// Spawn the HUD texts of a newly inserted chart
+pub fn spawn_hud(mut commands: Commands, game_state: Res<GameState>, config: Res<HudConfig>) {
+    let style = TextStyle {
+        color: Color::BLACK,
+        ..default()
+    };
+
+    let combo = Text::from_sections([
+        TextSection::new(COMBO_LABEL, style.clone()),
+        TextSection::new("", style.clone()),
+    ]);
+    let title = Text::from_section(game_state.chart.songs_name.clone(), style.clone());
+    let time = Text::from_section("", style.clone());
+    let duration = config
+        .duration
+        .unwrap_or_else(|| chart_end_time(&game_state.chart));
+    let watermark = Text::from_section(
+        config.watermark.clone().unwrap_or_default(),
+        TextStyle {
+            color: Color::BLACK.with_alpha(0.6),
+            ..default()
+        },
+    );
+
+    for (text, item, anchor) in [
+        (combo, HudText::Combo, Anchor::CenterRight),
+        (title, HudText::Title, Anchor::TopLeft),
+        (time, HudText::Time { duration }, Anchor::TopLeft),
+        (watermark, HudText::Watermark, Anchor::Center),
+    ] {
+        commands.spawn((
+            Text2dBundle {
+                text,
+                text_anchor: anchor,
+                visibility: Visibility::Hidden,
+                ..default()
+            },
+            GameEntity,
+            item,
+        ));
+    }
+}
+
+/// Update the HUD texts and lay them out over the visible area
+pub fn update_hud(
+    game_state: Res<GameState>,
+    score: Res<Score>,
+    config: Res<HudConfig>,
+    playfield: Res<Playfield>,
+    window: Query<&Window, With<PrimaryWindow>>,
+    mut texts: Query<(&HudText, &mut Text, &mut Transform, &mut Visibility)>,
+) {
+    let view = match window.get_single() {
+        Ok(window) => Vec2::new(window.width(), window.height()),
+        Err(_) => Vec2::new(playfield.width, playfield.height),
+    };
+    // Same factors as `cvs.width / 360` and `cvs.height / 640` in the JS player
+    let kx = view.x / 360.0;
+    let ky = view.y / 640.0;
+    let top = playfield.camera_offset() + view.y / 2.0;
+    let left = -view.x / 2.0;
+
+    let combo = calculate_combo(score.0.combo);
+
+    for (item, mut text, mut transform, mut visibility) in texts.iter_mut() {
+        let (position, font_sizes, shown) = match item {
+            HudText::Combo => {
+                set_section(&mut text, 1, combo.to_string());
+                (
+                    Vec2::new(view.x / 2.0 - 25.0 * kx, top - 50.0 * ky),
+                    [20.0, 30.0],
+                    combo > 0,
+                )
+            }
+            HudText::Title => (
+                Vec2::new(left + 10.0 * kx, top - 10.0 * ky),
+                [12.0; 2],
+                true,
+            ),
+            HudText::Time { duration } => {
+                let elapsed = game_state.current_time.clamp(0.0, *duration);
+                set_section(
+                    &mut text,
+                    0,
+                    format!("{} / {}", format_time(elapsed), format_time(*duration)),
+                );
+                (
+                    Vec2::new(left + 10.0 * kx, top - 26.0 * ky),
+                    [10.0; 2],
+                    true,
+                )
+            }
+            HudText::Watermark => (
+                Vec2::new(0.0, top - 350.0 * ky),
+                [12.0; 2],
+                config.watermark.is_some(),
+            ),
+        };
+
+        let font_sizes = font_sizes.map(|size| size * kx);
+        let resized = text
+            .sections
+            .iter()
+            .zip(font_sizes)
+            .any(|(section, size)| section.style.font_size != size);
+        if resized {
+            for (section, size) in text.sections.iter_mut().zip(font_sizes) {
+                section.style.font_size = size;
+            }
+        }
+        transform.set_if_neq(Transform::from_translation(position.extend(HUD_Z)));
+        visibility.set_if_neq(if shown {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        });
+    }
+}
+
+/// Replace a section's text, leaving the text untouched (and not re-laid out) when equal
+fn set_section(text: &mut Mut<Text>, index: usize, value: String) {
+    if text
+        .sections
+        .get(index)
+        .is_some_and(|section| section.value != value)
+    {
+        text.sections[index].value = value;
+    }
+}
+
+/// `m:ss`
+fn format_time(seconds: f64) -> String {
+    let seconds = seconds.max(0.0) as u64;
+    format!("{}:{:02}", seconds / 60, seconds % 60)
+}
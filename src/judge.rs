@@ -0,0 +1,600 @@
+//! Judgement of player input.
+//!
+//! Rizline judges by time only: any press hits the earliest tap or hold in
+//! its window, wherever it lands. Input is fed as timestamped events in
+//! chart seconds; the result depends only on the events and the chart, so
+//! replaying the same events reproduces the same judgements.
+
+use std::collections::HashSet;
+
+use serde::{Deserialize, Serialize};
+
+use crate::chart::{Chart, NoteType};
+use crate::timing::TimingMap;
+
+/// Whether notes are hit automatically or by the player
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PlayMode {
+    #[default]
+    Autoplay,
+    Play,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Judgement {
+    Perfect,
+    Good,
+    Miss,
+}
+
+/// Timing windows in seconds, on each side of the note time
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct JudgeWindows {
+    pub perfect: f64,
+    pub good: f64,
+}
+
+impl Default for JudgeWindows {
+    fn default() -> Self {
+        Self {
+            perfect: 0.045,
+            good: 0.09,
+        }
+    }
+}
+
+impl JudgeWindows {
+    /// Judgement of a press `offset` seconds from the note, if within the windows
+    pub fn judge(&self, offset: f64) -> Option<Judgement> {
+        if offset.abs() <= self.perfect {
+            Some(Judgement::Perfect)
+        } else if offset.abs() <= self.good {
+            Some(Judgement::Good)
+        } else {
+            None
+        }
+    }
+}
+
+/// Key, mouse button or finger producing input
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum InputSource {
+    /// Keyboard key, by name
+    Key(String),
+    /// Mouse button, by name
+    Mouse(String),
+    /// Touch, by id
+    Touch(u64),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum InputAction {
+    Down,
+    Up,
+}
+
+/// Timestamped input
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InputEvent {
+    /// Chart time in seconds. Bevy's input carries no timestamps, so the
+    /// player stamps events with the time of the frame that saw them:
+    /// recorded input is only as precise as the frame rate (about 33 ms at
+    /// 30 fps, against a 45 ms perfect window).
+    pub time: f64,
+    pub action: InputAction,
+    pub source: InputSource,
+    /// Pointer x in playfield widths from the center; `None` for keys
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub x: Option<f32>,
+}
+
+/// Judgement of one note
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NoteResult {
+    pub judgement: Judgement,
+    /// Time of the hit (or of the miss), in seconds
+    pub time: f64,
+    /// Press time minus note time in seconds; 0 for drags and misses
+    pub offset: f64,
+}
+
+/// Judgement progress of a note
+#[derive(Debug, Clone)]
+enum Progress {
+    Pending,
+    /// Hold whose head was hit and which is still held by `source`
+    Holding {
+        source: InputSource,
+        head: NoteResult,
+    },
+    Done(NoteResult),
+    /// Before the start of the session; never judged
+    Skipped,
+}
+
+#[derive(Debug, Clone)]
+struct JudgeNote {
+    line_index: usize,
+    note_index: usize,
+    note_type: NoteType,
+    time: f64,
+    /// End of a hold in seconds, the note time for other notes
+    end_time: f64,
+    progress: Progress,
+}
+
+/// Hit of a note, in the order hits happened
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Hit {
+    pub line_index: usize,
+    pub note_index: usize,
+    pub note_type: NoteType,
+    pub time: f64,
+}
+
+/// Judges a stream of input events against a chart
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "player", derive(bevy::prelude::Resource))]
+pub struct Judge {
+    windows: JudgeWindows,
+    /// Notes sorted by time
+    notes: Vec<JudgeNote>,
+    /// Index into `notes` by line and note
+    lookup: Vec<Vec<usize>>,
+    /// Notes before this index are all resolved
+    first_open: usize,
+    held: HashSet<InputSource>,
+    hits: Vec<Hit>,
+    /// Start of the session in seconds
+    start: f64,
+    time: f64,
+}
+
+impl Judge {
+    /// Judge `chart` from `start` seconds; earlier notes are skipped
+    pub fn new(chart: &Chart, windows: JudgeWindows, start: f64) -> Self {
+        let timing = TimingMap::from_chart(chart);
+        let seconds = |tick: f64| timing.tick_to_seconds(tick);
+
+        let mut notes = Vec::new();
+        for (line_index, line) in chart.lines.iter().enumerate() {
+            for (note_index, note) in line.notes.iter().enumerate() {
+                let note_type = NoteType::from(note.note_type);
+                let time = seconds(note.time);
+                let end_time = match note_type {
+                    NoteType::Hold => note
+                        .other_informations
+                        .first()
+                        .map_or(time, |&end| seconds(end).max(time)),
+                    _ => time,
+                };
+                notes.push(JudgeNote {
+                    line_index,
+                    note_index,
+                    note_type,
+                    time,
+                    end_time,
+                    progress: if time < start {
+                        Progress::Skipped
+                    } else {
+                        Progress::Pending
+                    },
+                });
+            }
+        }
+        notes.sort_by(|a, b| a.time.total_cmp(&b.time));
+
+        let mut lookup: Vec<Vec<usize>> = chart
+            .lines
+            .iter()
+            .map(|line| vec![0; line.notes.len()])
+            .collect();
+        for (i, note) in notes.iter().enumerate() {
+            lookup[note.line_index][note.note_index] = i;
+        }
+
+        let mut judge = Self {
+            windows,
+            notes,
+            lookup,
+            first_open: 0,
+            held: HashSet::new(),
+            hits: Vec::new(),
+            start,
+            time: start,
+        };
+        judge.skip_resolved();
+        judge
+    }
+
+    /// Start over from `start` seconds, e.g. after a seek
+    pub fn restart(&mut self, start: f64) {
+        for note in &mut self.notes {
+            note.progress = if note.time < start {
+                Progress::Skipped
+            } else {
+                Progress::Pending
+            };
+        }
+        self.first_open = 0;
+        self.held.clear();
+        self.hits.clear();
+        self.start = start;
+        self.time = start;
+        self.skip_resolved();
+    }
+
+    pub fn windows(&self) -> JudgeWindows {
+        self.windows
+    }
+
+    /// Start of the session; earlier notes are skipped
+    pub fn start(&self) -> f64 {
+        self.start
+    }
+
+    /// Time up to which input has been judged
+    pub fn time(&self) -> f64 {
+        self.time
+    }
+
+    /// Hits so far, in the order they happened
+    pub fn hits(&self) -> &[Hit] {
+        &self.hits
+    }
+
+    /// Final judgement of a note, once known
+    pub fn result(&self, line_index: usize, note_index: usize) -> Option<NoteResult> {
+        let index = *self.lookup.get(line_index)?.get(note_index)?;
+        match self.notes[index].progress {
+            Progress::Done(result) => Some(result),
+            _ => None,
+        }
+    }
+
+    /// Whether the head of a note was hit, even if the hold is still going on
+    pub fn is_hit(&self, line_index: usize, note_index: usize) -> bool {
+        let Some(&index) = self
+            .lookup
+            .get(line_index)
+            .and_then(|line| line.get(note_index))
+        else {
+            return false;
+        };
+        match &self.notes[index].progress {
+            Progress::Holding { .. } => true,
+            Progress::Done(result) => result.judgement != Judgement::Miss,
+            _ => false,
+        }
+    }
+
+    /// Whether every note has been judged
+    pub fn is_finished(&self) -> bool {
+        self.first_open >= self.notes.len()
+    }
+
+    /// Judge an input event; events must come in time order
+    pub fn input(&mut self, event: &InputEvent) {
+        self.advance(event.time);
+        let time = event.time;
+
+        match event.action {
+            InputAction::Down => {
+                if !self.held.insert(event.source.clone()) {
+                    return;
+                }
+                self.press(&event.source, time);
+            }
+            InputAction::Up => {
+                self.held.remove(&event.source);
+                self.release(&event.source, time);
+            }
+        }
+    }
+
+    /// Judge everything that resolves by `time` without further input:
+    /// drags passing while input is held, completed holds and misses
+    pub fn advance(&mut self, time: f64) {
+        if time < self.time {
+            return;
+        }
+        self.time = time;
+
+        let good = self.windows.good;
+        let holding_any = !self.held.is_empty();
+        for i in self.first_open..self.notes.len() {
+            let note = &self.notes[i];
+            if note.time - good > time {
+                break;
+            }
+            let resolved = match (&note.progress, note.note_type) {
+                (Progress::Pending, NoteType::Drag) if note.time <= time && holding_any => {
+                    Some(NoteResult {
+                        judgement: Judgement::Perfect,
+                        time: note.time,
+                        offset: 0.0,
+                    })
+                }
+                (Progress::Pending, _) if note.time + good < time => Some(NoteResult {
+                    judgement: Judgement::Miss,
+                    time: note.time + good,
+                    offset: 0.0,
+                }),
+                (Progress::Holding { head, .. }, _) if note.end_time <= time => Some(*head),
+                _ => None,
+            };
+            if let Some(result) = resolved {
+                let hit = result.judgement != Judgement::Miss
+                    && matches!(note.progress, Progress::Pending);
+                self.finish(i, result, hit);
+            }
+        }
+        self.skip_resolved();
+    }
+
+    fn press(&mut self, source: &InputSource, time: f64) {
+        let good = self.windows.good;
+
+        // Drags passed just before the press still count
+        for i in self.first_open..self.notes.len() {
+            let note = &self.notes[i];
+            if note.time > time {
+                break;
+            }
+            if note.note_type == NoteType::Drag
+                && matches!(note.progress, Progress::Pending)
+                && time - note.time <= good
+            {
+                let result = NoteResult {
+                    judgement: Judgement::Perfect,
+                    time,
+                    offset: 0.0,
+                };
+                self.finish(i, result, true);
+            }
+        }
+
+        // One press hits the earliest tap or hold within its window
+        let target = (self.first_open..self.notes.len())
+            .take_while(|&i| self.notes[i].time - good <= time)
+            .find(|&i| {
+                let note = &self.notes[i];
+                note.note_type != NoteType::Drag && matches!(note.progress, Progress::Pending)
+            });
+        let Some(i) = target else {
+            return;
+        };
+        let offset = time - self.notes[i].time;
+        let Some(judgement) = self.windows.judge(offset) else {
+            return;
+        };
+        let result = NoteResult {
+            judgement,
+            time,
+            offset,
+        };
+
+        if self.notes[i].note_type == NoteType::Hold && self.notes[i].end_time > time {
+            self.notes[i].progress = Progress::Holding {
+                source: source.clone(),
+                head: result,
+            };
+            self.push_hit(i, time);
+        } else {
+            self.finish(i, result, true);
+        }
+        self.skip_resolved();
+    }
+
+    /// Releasing a hold before its end (give or take the good window) misses it
+    fn release(&mut self, source: &InputSource, time: f64) {
+        let good = self.windows.good;
+        for i in self.first_open..self.notes.len() {
+            let note = &self.notes[i];
+            if note.time > time {
+                break;
+            }
+            let Progress::Holding {
+                source: holder,
+                head,
+            } = &note.progress
+            else {
+                continue;
+            };
+            if holder != source {
+                continue;
+            }
+            let result = if time >= note.end_time - good {
+                *head
+            } else {
+                NoteResult {
+                    judgement: Judgement::Miss,
+                    time,
+                    offset: head.offset,
+                }
+            };
+            self.finish(i, result, false);
+        }
+        self.skip_resolved();
+    }
+
+    fn finish(&mut self, index: usize, result: NoteResult, hit: bool) {
+        self.notes[index].progress = Progress::Done(result);
+        if hit {
+            self.push_hit(index, result.time);
+        }
+    }
+
+    fn push_hit(&mut self, index: usize, time: f64) {
+        let note = &self.notes[index];
+        self.hits.push(Hit {
+            line_index: note.line_index,
+            note_index: note.note_index,
+            note_type: note.note_type,
+            time,
+        });
+    }
+
+    fn skip_resolved(&mut self) {
+        while self
+            .notes
+            .get(self.first_open)
+            .is_some_and(|note| matches!(note.progress, Progress::Done(_) | Progress::Skipped))
+        {
+            self.first_open += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::chart::tests::test_chart;
+
+    fn note(note_type: NoteType, time: f64) -> Value {
+        json!({ "type": note_type as u8, "time": time, "floorPosition": 0.0, "otherInformations": [] })
+    }
+
+    fn hold(time: f64, end: f64) -> Value {
+        json!({ "type": 2, "time": time, "floorPosition": 0.0, "otherInformations": [end] })
+    }
+
+    fn judge(notes: Vec<Value>) -> Judge {
+        Judge::new(
+            &test_chart(Value::Array(notes)),
+            JudgeWindows::default(),
+            0.0,
+        )
+    }
+
+    fn key(action: InputAction, name: &str, time: f64) -> InputEvent {
+        InputEvent {
+            time,
+            action,
+            source: InputSource::Key(name.into()),
+            x: None,
+        }
+    }
+
+    fn judgement(judge: &Judge, note_index: usize) -> Option<Judgement> {
+        judge.result(0, note_index).map(|result| result.judgement)
+    }
+
+    #[test]
+    fn windows_include_their_edges() {
+        let windows = JudgeWindows::default();
+        assert_eq!(windows.judge(0.0), Some(Judgement::Perfect));
+        assert_eq!(windows.judge(0.045), Some(Judgement::Perfect));
+        assert_eq!(windows.judge(-0.045), Some(Judgement::Perfect));
+        assert_eq!(windows.judge(0.0451), Some(Judgement::Good));
+        assert_eq!(windows.judge(0.09), Some(Judgement::Good));
+        assert_eq!(windows.judge(-0.09), Some(Judgement::Good));
+        assert_eq!(windows.judge(0.0901), None);
+        assert_eq!(windows.judge(-0.0901), None);
+    }
+
+    #[test]
+    fn press_hits_only_the_earliest_pending_note() {
+        let mut judge = judge(vec![note(NoteType::Tap, 1.0), hold(1.05, 2.0)]);
+        judge.input(&key(InputAction::Down, "A", 1.02));
+        assert_eq!(judgement(&judge, 0), Some(Judgement::Perfect));
+        assert!(!judge.is_hit(0, 1));
+
+        judge.input(&key(InputAction::Down, "S", 1.06));
+        assert!(judge.is_hit(0, 1));
+        assert_eq!(judge.hits().len(), 2);
+    }
+
+    #[test]
+    fn press_outside_the_windows_hits_nothing() {
+        let mut judge = judge(vec![note(NoteType::Tap, 1.0)]);
+        judge.input(&key(InputAction::Down, "A", 0.85));
+        assert!(judge.hits().is_empty());
+        assert_eq!(judgement(&judge, 0), None);
+    }
+
+    #[test]
+    fn drags_hit_only_while_input_is_held() {
+        let mut judge = judge(vec![note(NoteType::Drag, 1.0), note(NoteType::Drag, 2.0)]);
+        judge.advance(1.5);
+        assert_eq!(judgement(&judge, 0), Some(Judgement::Miss));
+
+        judge.input(&key(InputAction::Down, "A", 1.8));
+        judge.advance(2.0);
+        assert_eq!(judgement(&judge, 1), Some(Judgement::Perfect));
+    }
+
+    #[test]
+    fn press_just_after_a_drag_hits_it() {
+        let mut judge = judge(vec![note(NoteType::Drag, 1.0)]);
+        judge.input(&key(InputAction::Down, "A", 1.05));
+        let result = judge.result(0, 0).unwrap();
+        assert_eq!(result.judgement, Judgement::Perfect);
+        assert_eq!(result.time, 1.05);
+    }
+
+    #[test]
+    fn hold_released_early_is_missed() {
+        let mut judge = judge(vec![hold(1.0, 3.0), hold(4.0, 6.0)]);
+        judge.input(&key(InputAction::Down, "A", 1.0));
+        assert!(judge.is_hit(0, 0));
+        judge.input(&key(InputAction::Up, "A", 2.5));
+        assert_eq!(judgement(&judge, 0), Some(Judgement::Miss));
+
+        // Within the good window of the end counts as held through
+        judge.input(&key(InputAction::Down, "A", 4.0));
+        judge.input(&key(InputAction::Up, "A", 5.95));
+        assert_eq!(judgement(&judge, 1), Some(Judgement::Perfect));
+    }
+
+    #[test]
+    fn hold_held_to_the_end_keeps_its_head_judgement() {
+        let mut judge = judge(vec![hold(1.0, 3.0)]);
+        judge.input(&key(InputAction::Down, "A", 1.06));
+        judge.advance(2.0);
+        assert_eq!(judgement(&judge, 0), None);
+        judge.advance(3.0);
+        assert_eq!(judgement(&judge, 0), Some(Judgement::Good));
+        assert!(judge.is_finished());
+    }
+
+    #[test]
+    fn held_key_does_not_press_again() {
+        let mut judge = judge(vec![note(NoteType::Tap, 1.0), note(NoteType::Tap, 1.1)]);
+        judge.input(&key(InputAction::Down, "A", 1.0));
+        judge.input(&key(InputAction::Down, "A", 1.1));
+        assert_eq!(judgement(&judge, 0), Some(Judgement::Perfect));
+        assert!(!judge.is_hit(0, 1));
+
+        judge.input(&key(InputAction::Up, "A", 1.12));
+        judge.input(&key(InputAction::Down, "A", 1.13));
+        assert_eq!(judgement(&judge, 1), Some(Judgement::Perfect));
+    }
+
+    #[test]
+    fn notes_are_missed_after_the_good_window() {
+        let mut judge = judge(vec![note(NoteType::Tap, 1.0)]);
+        judge.advance(1.08);
+        assert_eq!(judgement(&judge, 0), None);
+        judge.advance(1.1);
+        let result = judge.result(0, 0).unwrap();
+        assert_eq!(result.judgement, Judgement::Miss);
+        assert!((result.time - 1.09).abs() < 1e-9);
+        assert!(judge.hits().is_empty());
+    }
+
+    #[test]
+    fn notes_before_the_start_are_skipped() {
+        let chart = test_chart(json!([note(NoteType::Tap, 1.0), note(NoteType::Tap, 3.0)]));
+        let mut judge = Judge::new(&chart, JudgeWindows::default(), 2.0);
+        judge.advance(10.0);
+        assert_eq!(judgement(&judge, 0), None);
+        assert_eq!(judgement(&judge, 1), Some(Judgement::Miss));
+        assert!(judge.is_finished());
+    }
+}
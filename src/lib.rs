@@ -0,0 +1,44 @@
+//! Rizline chart model and time evaluation.
+//!
+//! The chart model, easing and timing are always available. Audio mixing is
+//! behind the `audio` feature and the Bevy player behind the `player` feature.
+
+pub mod chart;
+pub mod easing;
+pub mod format;
+pub mod game;
+pub mod judge;
+pub mod replay;
+pub mod score;
+pub mod timing;
+pub mod validate;
+
+#[cfg(feature = "audio")]
+pub mod audio;
+#[cfg(feature = "audio")]
+pub mod hitsound;
+#[cfg(feature = "audio")]
+pub mod mixdown;
+
+#[cfg(feature = "player")]
+pub mod cover;
+#[cfg(feature = "player")]
+pub mod effects;
+#[cfg(feature = "player")]
+pub mod hand;
+#[cfg(feature = "player")]
+pub mod headless;
+#[cfg(feature = "player")]
+pub mod hud;
+#[cfg(feature = "player")]
+pub mod play;
+#[cfg(feature = "player")]
+pub mod polyline;
+#[cfg(feature = "player")]
+pub mod rendering;
+#[cfg(feature = "player")]
+pub mod results;
+#[cfg(feature = "player")]
+pub mod revelation;
+
+pub use chart::Chart;
@@ -1,18 +1,176 @@
-mod chart;
-mod easing;
-mod game;
-mod rendering;
-mod timing;
+mod cli;
 
+use bevy::app::ScheduleRunnerPlugin;
 use bevy::prelude::*;
-use bevy::window::WindowMode;
+use bevy::render::pipelined_rendering::PipelinedRenderingPlugin;
+use bevy::render::render_asset::RenderAssetUsages;
+use bevy::render::settings::{Backends, PowerPreference, RenderCreation, WgpuSettings};
+use bevy::render::texture::{CompressedImageFormats, ImageSampler, ImageType};
+use bevy::render::RenderPlugin;
+use bevy::window::{ExitCondition, PrimaryWindow, WindowMode};
+use bevy::winit::WinitPlugin;
 use std::fs;
+use std::path::Path;
+use std::time::Duration;
 
-use chart::Chart;
-use game::{initialize_canvas_states, GameState};
-use rendering::{Background, RenderingPlugin, WINDOW_HEIGHT, WINDOW_WIDTH};
+use ch_rzl::audio::{
+    find_song_for_chart, AudioBuffer, SongPlayer, SILENT_CHANNELS, SILENT_SAMPLE_RATE,
+};
+use ch_rzl::chart::Chart;
+use ch_rzl::game::{chart_end_time, initialize_canvas_states, GameState};
+use ch_rzl::hand::HandConfig;
+use ch_rzl::headless::{FrameSink, HeadlessRenderPlugin, RenderJob};
+use ch_rzl::hitsound::{schedule_hits, HitSounds};
+use ch_rzl::hud::HudConfig;
+use ch_rzl::judge::{InputAction, InputEvent, InputSource, Judge, PlayMode};
+use ch_rzl::mixdown;
+use ch_rzl::play::{sync_judgements, InputQueue, ReplayPlayback, ReplayRecorder};
+use ch_rzl::rendering::{
+    spawn_background, update_game_time, Playfield, RenderingPlugin, WINDOW_HEIGHT, WINDOW_WIDTH,
+};
+use ch_rzl::replay::{chart_hash, Replay};
+use ch_rzl::results::ResultsConfig;
+use ch_rzl::score::ScoreModel;
+use ch_rzl::validate::{validate, Severity};
 
-fn main() {
+use cli::{CheckOptions, Command, MixOptions, PlayOptions, RenderOptions, ScoreOptions, USAGE};
+
+/// Keys hitting notes in play mode
+const PLAY_KEYS: [KeyCode; 8] = [
+    KeyCode::KeyA,
+    KeyCode::KeyS,
+    KeyCode::KeyD,
+    KeyCode::KeyF,
+    KeyCode::KeyJ,
+    KeyCode::KeyK,
+    KeyCode::KeyL,
+    KeyCode::Semicolon,
+];
+
+fn main() -> AppExit {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match cli::parse_args(&args) {
+        Ok(Command::Play(options)) => run_player(options),
+        Ok(Command::Render(options)) => run_render(options),
+        Ok(Command::Mix(options)) => run_mix(options),
+        Ok(Command::Score(options)) => run_score(options),
+        Ok(Command::Check(options)) => run_check(options),
+        Err(message) => {
+            if !message.is_empty() {
+                eprintln!("error: {}\n", message);
+            }
+            eprintln!("{}", USAGE);
+            AppExit::error()
+        }
+    }
+}
+
+/// Play a chart in a window
+fn run_player(options: PlayOptions) -> AppExit {
+    let song_path = options.song.or_else(|| find_song_for_chart(&options.chart));
+
+    let chart = match load_chart(&options.chart) {
+        Ok(chart) => chart,
+        Err(message) => {
+            eprintln!("{}", message);
+            return show_load_error(message);
+        }
+    };
+    let replay = match options
+        .replay
+        .as_deref()
+        .map(|path| load_replay(path, &options.chart))
+    {
+        Some(Ok(replay)) => Some(replay),
+        Some(Err(message)) => {
+            eprintln!("{}", message);
+            return AppExit::error();
+        }
+        None => None,
+    };
+    // A replay is judged with the windows and from the time it was recorded with
+    let windows = replay
+        .as_ref()
+        .map_or(options.windows, |replay| replay.windows);
+    let start = replay.as_ref().map_or(0.0, |replay| replay.start);
+
+    let mut app = App::new();
+    app.add_plugins(DefaultPlugins.set(WindowPlugin {
+        primary_window: Some(Window {
+            title: "CH-RZL Player".to_string(),
+            resolution: (WINDOW_WIDTH, WINDOW_HEIGHT).into(),
+            resizable: true,
+            mode: WindowMode::Windowed,
+            ..default()
+        }),
+        ..default()
+    }))
+    .add_plugins(RenderingPlugin)
+    .add_systems(Startup, setup)
+    .add_systems(Update, keyboard_input.before(update_game_time))
+    .add_systems(
+        Update,
+        play_input.after(update_game_time).before(sync_judgements),
+    );
+
+    let mut hud = HudConfig {
+        watermark: options.watermark,
+        duration: None,
+    };
+
+    // The song clock drives the game when audio is available
+    if let Some(player) = open_song_player(song_path.as_deref(), &chart, options.mode) {
+        hud.duration = Some(player.duration()).filter(|&duration| duration > 0.0);
+        player.seek(start);
+        app.insert_non_send_resource(player);
+    }
+    app.insert_resource(ResultsConfig {
+        song_end: hud.duration,
+        output: options.result,
+    });
+    app.insert_resource(hud);
+
+    if options.mode == PlayMode::Play {
+        app.insert_resource(Judge::new(&chart, windows, start));
+    }
+    if let Some(path) = options.record {
+        match fs::read(&options.chart) {
+            Ok(content) => {
+                let replay = Replay::new(&chart, chart_hash(&content), windows, start);
+                app.insert_resource(ReplayRecorder::new(replay, path));
+            }
+            Err(e) => eprintln!(
+                "Not recording a replay, cannot read chart {}: {}",
+                options.chart.display(),
+                e
+            ),
+        }
+    }
+    if let Some(replay) = replay {
+        println!(
+            "Playing back replay with {} input events",
+            replay.events.len()
+        );
+        app.insert_resource(ReplayPlayback::new(replay));
+    }
+
+    insert_hand_config(&mut app, options.hand, options.hand_sprite.as_deref());
+
+    let mut game_state = GameState::new(chart);
+    game_state.play_mode = options.mode;
+    game_state.current_time = start;
+    initialize_canvas_states(&mut game_state);
+    app.insert_resource(game_state);
+
+    app.run()
+}
+
+/// Message of a chart that failed to load, shown in place of the game
+#[derive(Resource)]
+struct LoadError(String);
+
+/// Open a window reporting why the chart could not be loaded; fails once it is closed
+fn show_load_error(message: String) -> AppExit {
     App::new()
         .add_plugins(DefaultPlugins.set(WindowPlugin {
             primary_window: Some(Window {
@@ -24,57 +182,399 @@ fn main() {
             }),
             ..default()
         }))
-        .add_plugins(RenderingPlugin)
-        .add_systems(Startup, setup)
-        .add_systems(Update, keyboard_input)
+        .insert_resource(LoadError(message))
+        .add_systems(Startup, spawn_load_error)
         .run();
+    AppExit::error()
 }
 
-fn setup(mut commands: Commands) {
-    // Load chart from file or use default
-    let chart_path = std::env::args().nth(1).unwrap_or_else(|| "morimoriatsushi0IN.json".to_string());
-    
-    let chart = if let Ok(json) = fs::read_to_string(&chart_path) {
-        match Chart::from_json(&json) {
-            Ok(c) => {
-                println!("Loaded chart: {}", chart_path);
-                c
-            }
+fn spawn_load_error(mut commands: Commands, error: Res<LoadError>) {
+    commands.spawn(Camera2dBundle::default());
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                flex_direction: FlexDirection::Column,
+                padding: UiRect::all(Val::Px(24.0)),
+                row_gap: Val::Px(16.0),
+                ..default()
+            },
+            background_color: Color::srgb(0.12, 0.12, 0.14).into(),
+            ..default()
+        })
+        .with_children(|panel| {
+            panel.spawn(TextBundle::from_section(
+                "Cannot play this chart",
+                TextStyle {
+                    font_size: 28.0,
+                    color: Color::srgb(1.0, 0.45, 0.45),
+                    ..default()
+                },
+            ));
+            panel.spawn(TextBundle::from_section(
+                error.0.clone(),
+                TextStyle {
+                    font_size: 16.0,
+                    color: Color::WHITE,
+                    ..default()
+                },
+            ));
+        });
+}
+
+/// Render a chart offscreen at a fixed frame rate, without a window.
+/// Any wgpu adapter is accepted, so a software rasterizer (llvmpipe,
+/// lavapipe, WARP) is used when no GPU is present.
+fn run_render(options: RenderOptions) -> AppExit {
+    let song_path = options
+        .song
+        .clone()
+        .or_else(|| find_song_for_chart(&options.chart));
+    let chart = match load_chart(&options.chart) {
+        Ok(chart) => chart,
+        Err(message) => {
+            eprintln!("{}", message);
+            return AppExit::error();
+        }
+    };
+    let replay = match options
+        .replay
+        .as_deref()
+        .map(|path| load_replay(path, &options.chart))
+    {
+        Some(Ok(replay)) => Some(replay),
+        Some(Err(message)) => {
+            eprintln!("{}", message);
+            return AppExit::error();
+        }
+        None => None,
+    };
+
+    let song_length = song_path
+        .as_deref()
+        .and_then(|path| AudioBuffer::decode(path).ok())
+        .map(|song| song.duration());
+    let duration = options.duration.unwrap_or_else(|| {
+        song_length.unwrap_or_else(|| chart_end_time(&chart) + 1.0) - options.start
+    });
+    let hud = HudConfig {
+        watermark: options.watermark.clone(),
+        duration: song_length,
+    };
+    let results = ResultsConfig {
+        song_end: song_length,
+        output: None,
+    };
+
+    let sink = match FrameSink::open(
+        &options.output,
+        options.width,
+        options.height,
+        options.fps,
+        song_path.as_deref(),
+        options.start,
+    ) {
+        Ok(sink) => sink,
+        Err(e) => {
+            eprintln!("Failed to open render output: {}", e);
+            return AppExit::error();
+        }
+    };
+    let job = RenderJob::new(
+        options.width,
+        options.height,
+        options.fps,
+        options.start,
+        duration,
+        sink,
+    );
+    println!(
+        "Rendering {} frames at {}x{}, {} fps",
+        job.total_frames, options.width, options.height, options.fps
+    );
+
+    let judge = replay
+        .as_ref()
+        .map(|replay| Judge::new(&chart, replay.windows, replay.start));
+    let mut game_state = GameState::new(chart);
+    initialize_canvas_states(&mut game_state);
+
+    let mut app = App::new();
+    if let (Some(judge), Some(replay)) = (judge, replay) {
+        game_state.play_mode = PlayMode::Play;
+        app.insert_resource(judge)
+            .insert_resource(ReplayPlayback::new(replay));
+    }
+
+    app.add_plugins(
+        DefaultPlugins
+            .set(WindowPlugin {
+                primary_window: None,
+                exit_condition: ExitCondition::DontExit,
+                close_when_requested: false,
+            })
+            .set(RenderPlugin {
+                render_creation: RenderCreation::Automatic(WgpuSettings {
+                    backends: Some(Backends::all()),
+                    power_preference: PowerPreference::HighPerformance,
+                    ..default()
+                }),
+                synchronous_pipeline_compilation: true,
+            })
+            .disable::<WinitPlugin>()
+            .disable::<PipelinedRenderingPlugin>()
+            .disable::<bevy::audio::AudioPlugin>(),
+    )
+    .add_plugins(ScheduleRunnerPlugin::run_loop(Duration::ZERO))
+    .add_plugins(RenderingPlugin)
+    .add_plugins(HeadlessRenderPlugin)
+    .insert_resource(Playfield::for_output(options.width, options.height))
+    .insert_resource(job)
+    .insert_resource(hud)
+    .insert_resource(results)
+    .insert_resource(game_state);
+    insert_hand_config(&mut app, options.hand, options.hand_sprite.as_deref());
+
+    app.run()
+}
+
+/// Mix the chart's hit sounds into the song and write a WAV file
+fn run_mix(options: MixOptions) -> AppExit {
+    let chart = match load_chart(&options.chart) {
+        Ok(chart) => chart,
+        Err(message) => {
+            eprintln!("{}", message);
+            return AppExit::error();
+        }
+    };
+
+    let song = match AudioBuffer::decode(&options.song) {
+        Ok(song) => song,
+        Err(e) => {
+            eprintln!("Failed to load song {}: {}", options.song.display(), e);
+            return AppExit::error();
+        }
+    };
+    let effects = match HitSounds::load(
+        options.hit_sounds.as_deref(),
+        song.channels,
+        song.sample_rate,
+    ) {
+        Ok(effects) => effects,
+        Err(e) => {
+            eprintln!("Failed to load hit sounds: {}", e);
+            return AppExit::error();
+        }
+    };
+
+    let hits = schedule_hits(&chart, song.sample_rate);
+    let mixed = mixdown::mix_hits(&song, &effects, &hits);
+
+    match mixdown::write_wav(&options.output, &mixed, song.channels, song.sample_rate) {
+        Ok(()) => {
+            println!(
+                "Mixed {} hit sounds into {}",
+                hits.len(),
+                options.output.display()
+            );
+            AppExit::Success
+        }
+        Err(e) => {
+            eprintln!("Failed to write {}: {}", options.output.display(), e);
+            AppExit::error()
+        }
+    }
+}
+
+/// Judge a replay without graphics and print its score as JSON
+fn run_score(options: ScoreOptions) -> AppExit {
+    let chart = match load_chart(&options.chart) {
+        Ok(chart) => chart,
+        Err(message) => {
+            eprintln!("{}", message);
+            return AppExit::error();
+        }
+    };
+    let replay = match load_replay(&options.replay, &options.chart) {
+        Ok(replay) => replay,
+        Err(message) => {
+            eprintln!("{}", message);
+            return AppExit::error();
+        }
+    };
+
+    let judge = replay.judge(&chart);
+    let summary = ScoreModel::new(&chart).summarize_judge(&judge);
+    match serde_json::to_string_pretty(&summary) {
+        Ok(json) => {
+            println!("{}", json);
+            AppExit::Success
+        }
+        Err(e) => {
+            eprintln!("Failed to serialize score: {}", e);
+            AppExit::error()
+        }
+    }
+}
+
+/// Check a chart and report its problems, failing if any is an error
+fn run_check(options: CheckOptions) -> AppExit {
+    let chart = match fs::read_to_string(&options.chart) {
+        Ok(json) => Chart::from_json(&json),
+        Err(e) => {
+            eprintln!("Failed to read chart {}: {}", options.chart.display(), e);
+            return AppExit::error();
+        }
+    };
+    let chart = match chart {
+        Ok(chart) => chart,
+        Err(e) => {
+            eprintln!("Failed to parse chart {}: {}", options.chart.display(), e);
+            return AppExit::error();
+        }
+    };
+
+    let diagnostics = validate(&chart);
+    let count = |severity| {
+        diagnostics
+            .iter()
+            .filter(|d| d.severity == severity)
+            .count()
+    };
+    let errors = count(Severity::Error);
+
+    if options.json {
+        match serde_json::to_string_pretty(&diagnostics) {
+            Ok(json) => println!("{}", json),
             Err(e) => {
-                eprintln!("Failed to parse chart {}: {}", chart_path, e);
-                create_empty_chart()
+                eprintln!("Failed to serialize diagnostics: {}", e);
+                return AppExit::error();
             }
         }
+    } else if diagnostics.is_empty() {
+        println!("{}: no problems found", options.chart.display());
+    } else {
+        for diagnostic in &diagnostics {
+            println!("{}", diagnostic);
+        }
+        println!(
+            "{}: {} problems, {} errors, {} warnings, {} infos",
+            options.chart.display(),
+            diagnostics.len(),
+            errors,
+            count(Severity::Warn),
+            count(Severity::Info)
+        );
+    }
+
+    if errors > 0 {
+        AppExit::error()
     } else {
-        eprintln!("Chart file not found: {}", chart_path);
-        create_empty_chart()
+        AppExit::Success
+    }
+}
+
+/// Show the autoplay hand if `enabled`, drawn with the image at `sprite` if given
+fn insert_hand_config(app: &mut App, enabled: bool, sprite: Option<&Path>) {
+    let sprite = sprite.and_then(|path| match load_image(path) {
+        Ok(image) => Some(app.world_mut().resource_mut::<Assets<Image>>().add(image)),
+        Err(message) => {
+            eprintln!("Failed to load hand sprite {}: {}", path.display(), message);
+            None
+        }
+    });
+    app.insert_resource(HandConfig {
+        enabled,
+        sprite,
+        ..default()
+    });
+}
+
+/// Decode an image file, in any format Bevy is built to read
+fn load_image(path: &Path) -> Result<Image, String> {
+    let bytes = fs::read(path).map_err(|e| e.to_string())?;
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .unwrap_or("png");
+    Image::from_buffer(
+        &bytes,
+        ImageType::Extension(extension),
+        CompressedImageFormats::NONE,
+        true,
+        ImageSampler::Default,
+        RenderAssetUsages::default(),
+    )
+    .map_err(|e| e.to_string())
+}
+
+/// Load a replay, rejecting it unless it was recorded on the chart file at `chart_path`
+fn load_replay(replay_path: &Path, chart_path: &Path) -> Result<Replay, String> {
+    let replay = Replay::load(replay_path)
+        .map_err(|e| format!("Failed to load replay {}: {}", replay_path.display(), e))?;
+    let content = fs::read(chart_path)
+        .map_err(|e| format!("Failed to read chart {}: {}", chart_path.display(), e))?;
+    replay
+        .check_chart(&chart_hash(&content))
+        .map_err(|e| format!("Cannot use replay {}: {}", replay_path.display(), e))?;
+    Ok(replay)
+}
+
+/// Load and validate a chart; the error is a report ready to show
+fn load_chart(chart_path: &Path) -> Result<Chart, String> {
+    let chart = Chart::load(chart_path)
+        .map_err(|e| format!("Failed to load chart {}: {}", chart_path.display(), e))?;
+    println!("Loaded chart: {}", chart_path.display());
+    Ok(chart)
+}
+
+/// Open the audio output with the song and, in autoplay, the chart's hit sounds.
+/// Without a song, hit sounds are mixed over silence.
+fn open_song_player(song_path: Option<&Path>, chart: &Chart, mode: PlayMode) -> Option<SongPlayer> {
+    let song = match song_path.map(|path| (path, AudioBuffer::decode(path))) {
+        Some((path, Ok(song))) => {
+            println!("Loaded song: {} ({:.1}s)", path.display(), song.duration());
+            song
+        }
+        Some((path, Err(e))) => {
+            eprintln!("Failed to load song {}: {}", path.display(), e);
+            AudioBuffer::silence(SILENT_CHANNELS, SILENT_SAMPLE_RATE)
+        }
+        None => {
+            eprintln!("No song found for chart, playing hit sounds only");
+            AudioBuffer::silence(SILENT_CHANNELS, SILENT_SAMPLE_RATE)
+        }
     };
 
-    let mut game_state = GameState::new(chart);
-    initialize_canvas_states(&mut game_state);
+    let effects = HitSounds::load(None, song.channels, song.sample_rate).unwrap_or_else(|e| {
+        eprintln!("Failed to load hit sounds: {}", e);
+        HitSounds::silent()
+    });
+    // In play mode hit sounds follow the player's hits instead
+    let hits = match mode {
+        PlayMode::Autoplay => schedule_hits(chart, song.sample_rate),
+        PlayMode::Play => Vec::new(),
+    };
 
-    commands.insert_resource(game_state);
+    match SongPlayer::new(song, effects, hits) {
+        Ok(player) => Some(player),
+        Err(e) => {
+            eprintln!("Audio disabled: {}", e);
+            None
+        }
+    }
+}
 
+fn setup(mut commands: Commands, playfield: Res<Playfield>) {
     // Setup 2D camera with offset for game coordinate system
     // Offset calculation: 200 * (WINDOW_HEIGHT / 640.0) = 284.375
     commands.spawn(Camera2dBundle {
-        transform: Transform::from_xyz(0.0, 284.375, 0.0),
+        transform: Transform::from_xyz(0.0, playfield.camera_offset(), 0.0),
         ..default()
     });
 
-    // Spawn background
-    commands.spawn((
-        SpriteBundle {
-            sprite: Sprite {
-                color: Color::WHITE,
-                custom_size: Some(Vec2::new(WINDOW_WIDTH * 2.0, WINDOW_HEIGHT * 2.0)),
-                ..default()
-            },
-            transform: Transform::from_xyz(0.0, 0.0, 0.0),
-            ..default()
-        },
-        Background,
-    ));
+    spawn_background(&mut commands, &playfield);
 
     println!("CH-RZL Player initialized!");
     println!("Controls:");
@@ -82,30 +582,112 @@ fn setup(mut commands: Commands) {
     println!("  R - Reset to beginning");
     println!("  Left/Right - Seek backward/forward");
     println!("  Up/Down - Adjust speed");
+    println!("  [/] - Adjust playback rate");
+    println!("  -/= - Adjust music volume");
+    println!("  9/0 - Adjust hit sound volume");
+    println!("  A S D F J K L ; / mouse / touch - Hit notes (with --play)");
+    println!("  V - Toggle revelation view");
+    println!("  ,/. - Adjust revelation zoom");
 }
 
 fn keyboard_input(
     keyboard: Res<ButtonInput<KeyCode>>,
     mut game_state: ResMut<GameState>,
+    song: Option<NonSend<SongPlayer>>,
 ) {
     // Toggle play/pause
     if keyboard.just_pressed(KeyCode::Space) {
         game_state.is_playing = !game_state.is_playing;
+        if let Some(song) = &song {
+            song.seek(game_state.current_time);
+            song.set_playing(game_state.is_playing);
+        }
         println!("Playing: {}", game_state.is_playing);
     }
 
     // Reset
     if keyboard.just_pressed(KeyCode::KeyR) {
-        game_state.current_time = 0.0;
+        game_state.seek(0.0);
+        if let Some(song) = &song {
+            song.seek(0.0);
+        }
         println!("Reset to beginning");
     }
 
     // Seek
     if keyboard.pressed(KeyCode::ArrowLeft) {
-        game_state.current_time = (game_state.current_time - 0.1).max(0.0);
+        let time = (game_state.current_time - 0.1).max(0.0);
+        game_state.seek(time);
+        if let Some(song) = &song {
+            song.seek(game_state.current_time);
+        }
     }
     if keyboard.pressed(KeyCode::ArrowRight) {
-        game_state.current_time += 0.1;
+        let time = game_state.current_time + 0.1;
+        game_state.seek(time);
+        if let Some(song) = &song {
+            song.seek(game_state.current_time);
+        }
+    }
+
+    // Playback rate adjustment
+    if keyboard.just_pressed(KeyCode::BracketRight) {
+        game_state.playback_rate = (game_state.playback_rate + 0.1).min(2.0);
+        if let Some(song) = &song {
+            song.set_rate(game_state.playback_rate);
+        }
+        println!("Playback rate: {:.1}x", game_state.playback_rate);
+    }
+    if keyboard.just_pressed(KeyCode::BracketLeft) {
+        game_state.playback_rate = (game_state.playback_rate - 0.1).max(0.1);
+        if let Some(song) = &song {
+            song.set_rate(game_state.playback_rate);
+        }
+        println!("Playback rate: {:.1}x", game_state.playback_rate);
+    }
+
+    // Volume adjustment
+    if keyboard.just_pressed(KeyCode::Equal) {
+        game_state.music_volume = (game_state.music_volume + 0.1).min(1.0);
+    }
+    if keyboard.just_pressed(KeyCode::Minus) {
+        game_state.music_volume = (game_state.music_volume - 0.1).max(0.0);
+    }
+    if keyboard.just_pressed(KeyCode::Digit0) {
+        game_state.effect_volume = (game_state.effect_volume + 0.1).min(1.0);
+    }
+    if keyboard.just_pressed(KeyCode::Digit9) {
+        game_state.effect_volume = (game_state.effect_volume - 0.1).max(0.0);
+    }
+    if keyboard.any_just_pressed([
+        KeyCode::Equal,
+        KeyCode::Minus,
+        KeyCode::Digit0,
+        KeyCode::Digit9,
+    ]) {
+        if let Some(song) = &song {
+            song.set_music_volume(game_state.music_volume);
+            song.set_effect_volume(game_state.effect_volume);
+        }
+        println!(
+            "Music volume: {:.0}%, hit sound volume: {:.0}%",
+            game_state.music_volume * 100.0,
+            game_state.effect_volume * 100.0
+        );
+    }
+
+    // Revelation view
+    if keyboard.just_pressed(KeyCode::KeyV) {
+        game_state.toggle_revelation();
+        println!("Revelation view: {}", game_state.is_revelation());
+    }
+    if keyboard.just_pressed(KeyCode::Comma) {
+        game_state.adjust_revelation_zoom(1.0 / 1.25);
+        println!("Revelation zoom: {:.2}", game_state.revelation_zoom);
+    }
+    if keyboard.just_pressed(KeyCode::Period) {
+        game_state.adjust_revelation_zoom(1.25);
+        println!("Revelation zoom: {:.2}", game_state.revelation_zoom);
     }
 
     // Speed adjustment
@@ -119,59 +701,81 @@ fn keyboard_input(
     }
 }
 
-fn create_empty_chart() -> Chart {
-    Chart {
-        file_version: 0,
-        songs_name: String::new(),
-        themes: vec![chart::Theme {
-            colors_list: vec![
-                chart::Color { r: 255, g: 255, b: 255, a: 255 },
-                chart::Color { r: 254, g: 212, b: 212, a: 255 },
-                chart::Color { r: 217, g: 217, b: 217, a: 255 },
-            ],
-        }],
-        challenge_times: vec![],
-        bpm: 120.0,
-        bpm_shifts: vec![chart::BpmShift {
-            time: 0.0,
-            value: 1.0,
-            ease_type: 0,
-            floor_position: 0.0,
-        }],
-        offset: 0.0,
-        lines: vec![],
-        canvas_moves: vec![chart::CanvasMove {
-            index: 0,
-            x_position_key_points: vec![chart::KeyPoint {
-                time: 0.0,
-                value: 0.0,
-                ease_type: 0,
-                floor_position: 0.0,
-                fp: Some(0.0),
-            }],
-            speed_key_points: vec![chart::KeyPoint {
-                time: 0.0,
-                value: 1.0,
-                ease_type: 0,
-                floor_position: 0.0,
-                fp: Some(0.0),
-            }],
-        }],
-        camera_move: chart::CameraMove {
-            scale_key_points: vec![chart::KeyPoint {
-                time: 0.0,
-                value: 1.0,
-                ease_type: 0,
-                floor_position: 0.0,
-                fp: None,
-            }],
-            x_position_key_points: vec![chart::KeyPoint {
-                time: 0.0,
-                value: 0.0,
-                ease_type: 0,
-                floor_position: 0.0,
-                fp: None,
-            }],
-        },
+/// Queue the presses and releases of play keys, mouse buttons and touches
+/// for judgement, stamped with the chart time of this frame (see `InputEvent::time`)
+#[allow(clippy::too_many_arguments)]
+fn play_input(
+    game_state: Res<GameState>,
+    keyboard: Res<ButtonInput<KeyCode>>,
+    mouse: Res<ButtonInput<MouseButton>>,
+    touches: Res<Touches>,
+    window: Query<&Window, With<PrimaryWindow>>,
+    playfield: Res<Playfield>,
+    playback: Option<Res<ReplayPlayback>>,
+    mut queue: ResMut<InputQueue>,
+) {
+    // A replay being played back stands in for the player
+    if game_state.play_mode != PlayMode::Play || !game_state.is_playing || playback.is_some() {
+        return;
+    }
+
+    let time = game_state.current_time;
+    let window = window.get_single().ok();
+    // Pointer x in playfield widths from the center
+    let pointer_x =
+        |position: Vec2| window.map(|window| (position.x - window.width() / 2.0) / playfield.width);
+    let mut push = |action, source, x| {
+        queue.events.push(InputEvent {
+            time,
+            action,
+            source,
+            x,
+        })
+    };
+
+    for key in PLAY_KEYS {
+        let source = || InputSource::Key(format!("{:?}", key));
+        if keyboard.just_pressed(key) {
+            push(InputAction::Down, source(), None);
+        }
+        if keyboard.just_released(key) {
+            push(InputAction::Up, source(), None);
+        }
+    }
+
+    let cursor_x = window
+        .and_then(|window| window.cursor_position())
+        .and_then(pointer_x);
+    for button in mouse.get_just_pressed() {
+        push(
+            InputAction::Down,
+            InputSource::Mouse(format!("{:?}", button)),
+            cursor_x,
+        );
+    }
+    for button in mouse.get_just_released() {
+        push(
+            InputAction::Up,
+            InputSource::Mouse(format!("{:?}", button)),
+            cursor_x,
+        );
+    }
+
+    for touch in touches.iter_just_pressed() {
+        push(
+            InputAction::Down,
+            InputSource::Touch(touch.id()),
+            pointer_x(touch.position()),
+        );
+    }
+    for touch in touches
+        .iter_just_released()
+        .chain(touches.iter_just_canceled())
+    {
+        push(
+            InputAction::Up,
+            InputSource::Touch(touch.id()),
+            pointer_x(touch.position()),
+        );
     }
 }
@@ -0,0 +1,48 @@
+//! Offline hit sound mixdown, the native port of mixAudio.py's `AudioMixer`
+
+use std::path::Path;
+
+use crate::audio::{AudioBuffer, AudioError};
+use crate::hitsound::{HitSounds, ScheduledHit};
+
+/// Overlay the hit sounds onto the song at their sample frames.
+/// The result is extended when a hit sound runs past the end of the song.
+pub fn mix_hits(song: &AudioBuffer, effects: &HitSounds, hits: &[ScheduledHit]) -> Vec<f32> {
+    let channels = song.channels as usize;
+    let mut mixed = song.samples.to_vec();
+
+    for hit in hits {
+        let effect = effects.sample_for(hit.note_type);
+        let start = hit.frame as usize * channels;
+        let end = start + effect.len();
+        if end > mixed.len() {
+            mixed.resize(end, 0.0);
+        }
+        for (out, sample) in mixed[start..end].iter_mut().zip(effect.iter()) {
+            *out += sample;
+        }
+    }
+
+    mixed
+}
+
+/// Write interleaved samples as a 16-bit PCM WAV file, clipping out-of-range samples
+pub fn write_wav(
+    path: &Path,
+    samples: &[f32],
+    channels: u16,
+    sample_rate: u32,
+) -> Result<(), AudioError> {
+    let spec = hound::WavSpec {
+        channels,
+        sample_rate,
+        bits_per_sample: 16,
+        sample_format: hound::SampleFormat::Int,
+    };
+    let mut writer = hound::WavWriter::create(path, spec).map_err(AudioError::Encode)?;
+    for &sample in samples {
+        let value = (sample.clamp(-1.0, 1.0) * i16::MAX as f32).round() as i16;
+        writer.write_sample(value).map_err(AudioError::Encode)?;
+    }
+    writer.finalize().map_err(AudioError::Encode)
+}
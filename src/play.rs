@@ -0,0 +1,182 @@
+//! Interactive play: input events queued by the app are judged by the
+//! `Judge` resource, whose results drive the note states and hit sounds.
+//! The judged input can be recorded to a replay, and a replay can stand in
+//! for the player's input.
+
+use std::path::PathBuf;
+
+use bevy::prelude::*;
+
+use crate::audio::SongPlayer;
+use crate::game::GameState;
+use crate::judge::{InputEvent, Judge, PlayMode};
+use crate::rendering::{HitCount, NoteStates};
+use crate::replay::Replay;
+
+/// Input events of the current frame, in time order, waiting to be judged
+#[derive(Resource, Default)]
+pub struct InputQueue {
+    pub events: Vec<InputEvent>,
+}
+
+/// Replay of the current session, saved to `path` once every note is judged,
+/// or with the input so far when the app exits first
+#[derive(Resource)]
+pub struct ReplayRecorder {
+    pub replay: Replay,
+    pub path: PathBuf,
+    saved: bool,
+}
+
+impl ReplayRecorder {
+    pub fn new(replay: Replay, path: PathBuf) -> Self {
+        Self {
+            replay,
+            path,
+            saved: false,
+        }
+    }
+
+    /// Drop the recorded input and record a new session from `start`
+    fn restart(&mut self, start: f64) {
+        self.replay.start = start;
+        self.replay.events.clear();
+        self.saved = false;
+    }
+
+    /// Write the replay unless this session was saved already
+    fn save(&mut self) {
+        if self.saved {
+            return;
+        }
+        self.saved = true;
+        match self.replay.save(&self.path) {
+            Ok(()) => println!("Saved replay to {}", self.path.display()),
+            Err(e) => eprintln!("Failed to save replay to {}: {}", self.path.display(), e),
+        }
+    }
+}
+
+/// Replay whose input is judged instead of the player's
+#[derive(Resource)]
+pub struct ReplayPlayback {
+    pub replay: Replay,
+    /// Next event to queue
+    next: usize,
+}
+
+impl ReplayPlayback {
+    pub fn new(replay: Replay) -> Self {
+        Self { replay, next: 0 }
+    }
+}
+
+/// Queue the replay's events up to the current time.
+/// Events before the judge's start were skipped over by a seek and are dropped.
+pub fn feed_replay(
+    game_state: Res<GameState>,
+    playback: Option<ResMut<ReplayPlayback>>,
+    judge: Option<Res<Judge>>,
+    mut queue: ResMut<InputQueue>,
+) {
+    let (Some(mut playback), Some(judge)) = (playback, judge) else {
+        return;
+    };
+    let time = game_state.current_time;
+    let events = &playback.replay.events;
+    let (next, start) = if game_state.seeked || time < judge.time() {
+        // A seek restarts the judge at the current time, and the replay with it
+        (events.partition_point(|event| event.time < time), time)
+    } else {
+        (playback.next, judge.start())
+    };
+    let end = next + events[next..].partition_point(|event| event.time <= time);
+    queue.events.extend(
+        events[next..end]
+            .iter()
+            .filter(|event| event.time >= start)
+            .cloned(),
+    );
+    playback.next = end;
+}
+
+/// Judge the queued input up to the current time, then copy the judgements
+/// into the note states and play the hit sounds of new hits
+#[allow(clippy::too_many_arguments)]
+pub fn sync_judgements(
+    mut game_state: ResMut<GameState>,
+    judge: Option<ResMut<Judge>>,
+    mut recorder: Option<ResMut<ReplayRecorder>>,
+    mut queue: ResMut<InputQueue>,
+    mut note_states: ResMut<NoteStates>,
+    mut hit_count: ResMut<HitCount>,
+    song: Option<NonSend<SongPlayer>>,
+) {
+    let seeked = std::mem::take(&mut game_state.seeked);
+    let Some(mut judge) = judge.filter(|_| game_state.play_mode == PlayMode::Play) else {
+        queue.events.clear();
+        return;
+    };
+
+    let time = game_state.current_time;
+    // Seeking, or the song clock pulling time back, starts a new session from there
+    if seeked || time < judge.time() {
+        judge.restart(time);
+        if let Some(recorder) = &mut recorder {
+            recorder.restart(time);
+        }
+    }
+
+    if let Some(recorder) = &mut recorder {
+        recorder.replay.events.extend(queue.events.iter().cloned());
+    }
+
+    let judged_hits = judge.hits().len();
+    for event in queue.events.drain(..) {
+        judge.input(&event);
+    }
+    judge.advance(time);
+
+    if let Some(recorder) = recorder.as_mut().filter(|_| judge.is_finished()) {
+        recorder.save();
+    }
+
+    if let Some(song) = &song {
+        for hit in &judge.hits()[judged_hits..] {
+            song.play_hit(hit.note_type);
+        }
+    }
+    hit_count.0 = judge.hits().len() as u32;
+
+    note_states
+        .states
+        .resize_with(game_state.chart.lines.len(), Vec::new);
+    for (line_index, (line, states)) in game_state
+        .chart
+        .lines
+        .iter()
+        .zip(&mut note_states.states)
+        .enumerate()
+    {
+        states.resize_with(line.notes.len(), Default::default);
+        for (note_index, state) in states.iter_mut().enumerate() {
+            state.is_hit = judge.is_hit(line_index, note_index);
+            state.is_play_hit = state.is_hit;
+            state.result = judge.result(line_index, note_index);
+        }
+    }
+}
+
+/// Save the replay of a session cut short by closing the window or quitting.
+/// Notes after the last recorded input are judged as misses when it is played back.
+pub fn save_replay_on_exit(
+    mut exit: EventReader<AppExit>,
+    recorder: Option<ResMut<ReplayRecorder>>,
+) {
+    if exit.read().next().is_none() {
+        return;
+    }
+    if let Some(mut recorder) = recorder {
+        recorder.save();
+    }
+}
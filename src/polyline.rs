@@ -0,0 +1,190 @@
+//! Triangle-strip meshes for chart lines.
+//!
+//! Every line is a single strip: each polyline contributes a pair of vertices
+//! (left, right) per point, and consecutive polylines are stitched together
+//! with degenerate triangles. Joins are mitered, falling back to a round join
+//! when the miter would exceed `MITER_LIMIT`.
+
+use std::f32::consts::PI;
+
+use bevy::prelude::*;
+use bevy::render::mesh::PrimitiveTopology;
+use bevy::render::render_asset::RenderAssetUsages;
+
+/// Longest miter, in multiples of the half width, before switching to a round join
+pub const MITER_LIMIT: f32 = 4.0;
+
+/// Largest angle covered by one triangle of a round join
+pub const ROUND_JOIN_STEP: f32 = PI / 8.0;
+
+/// Largest distance (world units) between a curve and its sampled polyline
+pub const FLATNESS_TOLERANCE: f32 = 0.25;
+
+/// Deepest subdivision of a curve, i.e. at most 2^6 = 64 pieces
+pub const MAX_SUBDIVISION_DEPTH: u32 = 6;
+
+/// Points closer than this are merged
+const MIN_POINT_DISTANCE: f32 = 1e-3;
+
+/// Point of a polyline with its vertex color
+#[derive(Clone, Copy)]
+pub struct PolylinePoint {
+    pub position: Vec2,
+    pub color: Color,
+}
+
+/// Vertex data of a triangle strip, reused from frame to frame
+#[derive(Default)]
+pub struct StripBuilder {
+    positions: Vec<[f32; 3]>,
+    colors: Vec<[f32; 4]>,
+}
+
+impl StripBuilder {
+    pub fn clear(&mut self) {
+        self.positions.clear();
+        self.colors.clear();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.positions.is_empty()
+    }
+
+    /// Append a polyline of the given width to the strip
+    pub fn add_polyline(&mut self, points: &[PolylinePoint], width: f32) {
+        let mut points = points.to_vec();
+        points.dedup_by(|b, a| a.position.distance(b.position) < MIN_POINT_DISTANCE);
+        if points.len() < 2 {
+            return;
+        }
+
+        let start = self.positions.len();
+        let half_width = width / 2.0;
+        let last = points.len() - 1;
+
+        for i in 0..points.len() {
+            let point = points[i];
+            let color = point.color.to_linear().to_f32_array();
+            let d0 = (point.position - points[i.saturating_sub(1)].position).normalize_or_zero();
+            let d1 = (points[(i + 1).min(last)].position - point.position).normalize_or_zero();
+
+            if i == 0 || i == last {
+                let normal = if i == 0 { d1.perp() } else { d0.perp() };
+                self.push_pair(
+                    point.position + normal * half_width,
+                    point.position - normal * half_width,
+                    color,
+                );
+                continue;
+            }
+
+            let (n0, n1) = (d0.perp(), d1.perp());
+            let miter = (n0 + n1).normalize_or_zero();
+            let cos = miter.dot(n0);
+            if cos > 1.0 / MITER_LIMIT {
+                let offset = miter * (half_width / cos);
+                self.push_pair(point.position + offset, point.position - offset, color);
+                continue;
+            }
+
+            // Round join: sweep the outer side around the point while the
+            // inner side stays put
+            let turn = n0.perp_dot(n1).atan2(n0.dot(n1));
+            let steps = (turn.abs() / ROUND_JOIN_STEP).ceil().max(1.0) as u32;
+            let inner_offset = if miter == Vec2::ZERO {
+                Vec2::ZERO
+            } else {
+                miter * half_width
+            };
+            for step in 0..=steps {
+                let normal = Vec2::from_angle(turn * step as f32 / steps as f32).rotate(n0);
+                if turn > 0.0 {
+                    // Turning left, the outer side is on the right
+                    self.push_pair(
+                        point.position + inner_offset,
+                        point.position - normal * half_width,
+                        color,
+                    );
+                } else {
+                    self.push_pair(
+                        point.position + normal * half_width,
+                        point.position - inner_offset,
+                        color,
+                    );
+                }
+            }
+        }
+
+        // Stitch to the previous polyline with degenerate triangles
+        if start > 0 {
+            let previous_end = start - 1;
+            self.positions.insert(start, self.positions[previous_end]);
+            self.colors.insert(start, self.colors[previous_end]);
+            self.positions.insert(start + 1, self.positions[start + 1]);
+            self.colors.insert(start + 1, self.colors[start + 1]);
+        }
+    }
+
+    fn push_pair(&mut self, left: Vec2, right: Vec2, color: [f32; 4]) {
+        self.positions.push(left.extend(0.0).to_array());
+        self.positions.push(right.extend(0.0).to_array());
+        self.colors.push(color);
+        self.colors.push(color);
+    }
+
+    /// Replace the vertices of `mesh` with the strip
+    pub fn write_to(&self, mesh: &mut Mesh) {
+        mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, self.positions.clone());
+        mesh.insert_attribute(Mesh::ATTRIBUTE_COLOR, self.colors.clone());
+    }
+}
+
+/// Empty triangle-strip mesh to be filled by `StripBuilder::write_to`
+pub fn strip_mesh() -> Mesh {
+    let mut mesh = Mesh::new(
+        PrimitiveTopology::TriangleStrip,
+        RenderAssetUsages::default(),
+    );
+    StripBuilder::default().write_to(&mut mesh);
+    mesh
+}
+
+/// Sample the curve `f` over `[0, 1]`, subdividing until every piece is
+/// within `FLATNESS_TOLERANCE` of the curve. Pushes `(t, f(t))` pairs,
+/// both ends included. `min_depth` forces subdivision of curves whose
+/// midpoint happens to lie on the chord (e.g. symmetric in-out eases).
+pub fn subdivide_curve(f: impl Fn(f32) -> Vec2, min_depth: u32, out: &mut Vec<(f32, Vec2)>) {
+    let start = f(0.0);
+    let end = f(1.0);
+    out.push((0.0, start));
+    subdivide(&f, (0.0, start), (1.0, end), 0, min_depth, out);
+}
+
+fn subdivide(
+    f: &impl Fn(f32) -> Vec2,
+    (t0, p0): (f32, Vec2),
+    (t1, p1): (f32, Vec2),
+    depth: u32,
+    min_depth: u32,
+    out: &mut Vec<(f32, Vec2)>,
+) {
+    if depth < MAX_SUBDIVISION_DEPTH {
+        let tm = (t0 + t1) / 2.0;
+        let pm = f(tm);
+        if depth < min_depth || distance_to_chord(pm, p0, p1) > FLATNESS_TOLERANCE {
+            subdivide(f, (t0, p0), (tm, pm), depth + 1, min_depth, out);
+            subdivide(f, (tm, pm), (t1, p1), depth + 1, min_depth, out);
+            return;
+        }
+    }
+    out.push((t1, p1));
+}
+
+fn distance_to_chord(point: Vec2, a: Vec2, b: Vec2) -> f32 {
+    let chord = b - a;
+    let length = chord.length();
+    if length < MIN_POINT_DISTANCE {
+        return point.distance(a);
+    }
+    chord.perp_dot(point - a).abs() / length
+}
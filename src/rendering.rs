@@ -1,19 +1,40 @@
 use bevy::prelude::*;
-use bevy::sprite::MaterialMesh2dBundle;
+use bevy::render::view::NoFrustumCulling;
+use bevy::sprite::{MaterialMesh2dBundle, Mesh2dHandle};
 
+use crate::audio::{SongPlayer, CLOCK_CORRECTION, MAX_CLOCK_DRIFT};
 use crate::chart::Color as ChartColor;
+use crate::cover::{spawn_cover, update_cover};
+use crate::effects::{build_hit_timeline, update_hit_effects, HitTimeline};
+use crate::game::{
+    calculate_mixed_color, compute_line_point, get_current_judge_ring_color, update_canvas_states,
+    GameState, NoteState,
+};
+use crate::hand::{spawn_hand, update_hand, HandConfig};
+use crate::hud::{spawn_hud, update_hud, HudConfig};
+use crate::judge::{Judgement, NoteResult, PlayMode};
+use crate::play::{feed_replay, save_replay_on_exit, sync_judgements, InputQueue};
+use crate::polyline::{strip_mesh, subdivide_curve, PolylinePoint, StripBuilder};
+use crate::results::{spawn_results, update_results, update_score, ResultsConfig, Score};
+use crate::revelation::{spawn_revelation_overlay, update_revelation_overlay};
 
 /// Default white color constant
-pub const WHITE_COLOR: ChartColor = ChartColor { r: 255, g: 255, b: 255, a: 255 };
+pub const WHITE_COLOR: ChartColor = ChartColor {
+    r: 255,
+    g: 255,
+    b: 255,
+    a: 255,
+};
 
 /// Line width for judge ring
 pub const JUDGE_RING_LINE_WIDTH: f32 = 5.0;
-use crate::easing::apply_ease;
-use crate::game::{
-    calculate_mixed_color, compute_line_point, get_current_judge_ring_color, update_canvas_states,
-    GameState, NoteState,
-};
-use crate::timing::{speed_to_fp, tick_to_seconds};
+
+/// Width of chart lines at camera scale 1
+pub const LINE_WIDTH: f32 = 3.0;
+
+/// Gap between the end of a segment and the start of the next one above
+/// which the line is broken (e.g. when it moves to another canvas)
+const LINE_BREAK_DISTANCE: f32 = 0.5;
 
 /// Window configuration
 pub const WINDOW_WIDTH: f32 = 565.0;
@@ -21,6 +42,37 @@ pub const WINDOW_HEIGHT: f32 = 710.0;
 #[allow(dead_code)]
 pub const ASPECT_RATIO: f32 = WINDOW_WIDTH / WINDOW_HEIGHT;
 
+/// Logical size of the playfield used for layout (world units)
+#[derive(Resource, Clone, Copy)]
+pub struct Playfield {
+    pub width: f32,
+    pub height: f32,
+}
+
+impl Default for Playfield {
+    fn default() -> Self {
+        Self {
+            width: WINDOW_WIDTH,
+            height: WINDOW_HEIGHT,
+        }
+    }
+}
+
+impl Playfield {
+    /// Playfield keeping the window width, with the aspect ratio of a `width`x`height` output
+    pub fn for_output(width: u32, height: u32) -> Self {
+        Self {
+            width: WINDOW_WIDTH,
+            height: WINDOW_WIDTH * height as f32 / width as f32,
+        }
+    }
+
+    /// Camera offset for the game coordinate system: 200 * (height / 640)
+    pub fn camera_offset(&self) -> f32 {
+        200.0 * (self.height / 640.0)
+    }
+}
+
 /// Marker component for game entities
 #[derive(Component)]
 pub struct GameEntity;
@@ -29,17 +81,14 @@ pub struct GameEntity;
 #[derive(Component)]
 pub struct Background;
 
-/// Marker for line segments
+/// Marker for the mesh of a chart line
 #[derive(Component)]
-#[allow(dead_code)]
-pub struct LineSegment {
+pub struct LineMesh {
     pub line_index: usize,
-    pub point_index: usize,
 }
 
 /// Marker for notes
-#[derive(Component)]
-#[allow(dead_code)]
+#[derive(Component, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct NoteEntity {
     pub line_index: usize,
     pub note_index: usize,
@@ -52,19 +101,31 @@ pub struct JudgeRing {
     pub line_index: usize,
 }
 
-/// Marker for hit effect
-#[derive(Component)]
-#[allow(dead_code)]
-pub struct HitEffect {
-    pub start_time: f64,
-    pub x: f32,
-    pub color: ChartColor,
+/// Side of a judge ring drawn by this entity
+#[derive(Component, Clone, Copy)]
+pub enum RingSide {
+    Top,
+    Bottom,
+    Left,
+    Right,
 }
 
-/// Marker for combo text
-#[derive(Component)]
-#[allow(dead_code)]
-pub struct ComboText;
+impl RingSide {
+    pub const ALL: [RingSide; 4] = [
+        RingSide::Top,
+        RingSide::Bottom,
+        RingSide::Left,
+        RingSide::Right,
+    ];
+}
+
+/// Part of a note drawn by this entity
+#[derive(Component, Clone, Copy)]
+pub enum NotePart {
+    Head,
+    Border,
+    HoldBody,
+}
 
 /// Note states resource
 #[derive(Resource, Default)]
@@ -72,6 +133,40 @@ pub struct NoteStates {
     pub states: Vec<Vec<NoteState>>,
 }
 
+/// Mesh and materials shared by all chart entities
+#[derive(Resource)]
+pub struct RenderHandles {
+    /// Unit square, sized through the entity's transform scale
+    pub quad: Handle<Mesh>,
+    pub black: Handle<ColorMaterial>,
+    /// Material of line meshes, tinted by their vertex colors
+    pub white: Handle<ColorMaterial>,
+    /// Material shared by every tap and hold note, in the theme's note color
+    pub note: Handle<ColorMaterial>,
+    /// Material shared by every drag note
+    pub drag: Handle<ColorMaterial>,
+}
+
+impl FromWorld for RenderHandles {
+    fn from_world(world: &mut World) -> Self {
+        let quad = world
+            .resource_mut::<Assets<Mesh>>()
+            .add(Rectangle::new(1.0, 1.0));
+        let mut materials = world.resource_mut::<Assets<ColorMaterial>>();
+        let black = materials.add(Color::BLACK);
+        let white = materials.add(Color::WHITE);
+        let note = materials.add(Color::WHITE);
+        let drag = materials.add(WHITE_COLOR.to_bevy_color());
+        Self {
+            quad,
+            black,
+            white,
+            note,
+            drag,
+        }
+    }
+}
+
 /// Hit count resource
 #[derive(Resource, Default)]
 pub struct HitCount(pub u32);
@@ -82,43 +177,253 @@ pub struct RenderingPlugin;
 impl Plugin for RenderingPlugin {
     fn build(&self, app: &mut App) {
         app.init_resource::<NoteStates>()
+            .init_resource::<Playfield>()
             .init_resource::<HitCount>()
-            .add_systems(Update, (
-                update_game_time,
-                update_rendering,
-            ).chain());
+            .init_resource::<RenderHandles>()
+            .init_resource::<HitTimeline>()
+            .init_resource::<HudConfig>()
+            .init_resource::<InputQueue>()
+            .init_resource::<ResultsConfig>()
+            .init_resource::<HandConfig>()
+            .init_resource::<Score>()
+            .add_systems(
+                Update,
+                (
+                    update_game_time,
+                    spawn_chart_entities.run_if(resource_added::<GameState>),
+                    spawn_cover.run_if(resource_added::<GameState>),
+                    spawn_hud.run_if(resource_added::<GameState>),
+                    spawn_revelation_overlay.run_if(resource_added::<GameState>),
+                    spawn_results.run_if(resource_added::<GameState>),
+                    spawn_hand.run_if(resource_added::<GameState>),
+                    feed_replay,
+                    sync_judgements,
+                    update_rendering,
+                    update_cover,
+                    build_hit_timeline.run_if(resource_added::<GameState>),
+                    update_hit_effects,
+                    update_hand,
+                    update_revelation_overlay,
+                    update_score,
+                    update_hud,
+                    update_results,
+                )
+                    .chain(),
+            )
+            .add_systems(Last, save_replay_on_exit);
     }
 }
 
+/// Spawn the full-screen background sprite
+pub fn spawn_background(commands: &mut Commands, playfield: &Playfield) {
+    commands.spawn((
+        SpriteBundle {
+            sprite: Sprite {
+                color: Color::WHITE,
+                custom_size: Some(Vec2::new(playfield.width * 2.0, playfield.height * 2.0)),
+                ..default()
+            },
+            transform: Transform::from_xyz(0.0, 0.0, 0.0),
+            ..default()
+        },
+        Background,
+    ));
+}
+
 /// Update game time based on audio or frame time
-fn update_game_time(
+pub fn update_game_time(
     time: Res<Time>,
     mut game_state: ResMut<GameState>,
+    song: Option<NonSend<SongPlayer>>,
 ) {
-    if game_state.is_playing {
-        game_state.current_time += time.delta_seconds_f64();
+    if !game_state.is_playing {
+        return;
+    }
+
+    game_state.current_time += time.delta_seconds_f64() * game_state.playback_rate;
+
+    // The song position is the master clock; the frame clock only smooths
+    // over the coarse updates coming from the audio thread
+    if let Some(song) = song {
+        let drift = song.position() - game_state.current_time;
+        if drift.abs() > MAX_CLOCK_DRIFT {
+            game_state.current_time = song.position();
+        } else {
+            game_state.current_time += drift * CLOCK_CORRECTION;
+        }
     }
 }
 
-/// Main rendering update system
-fn update_rendering(
+/// Placement of the shared unit quad
+#[derive(Clone, Copy)]
+pub(crate) struct Quad {
+    pub center: Vec2,
+    pub size: Vec2,
+    pub z: f32,
+    pub color: Color,
+}
+
+impl Quad {
+    pub fn new(center: Vec2, size: Vec2, z: f32, color: Color) -> Self {
+        Self {
+            center,
+            size,
+            z,
+            color,
+        }
+    }
+}
+
+/// Quads of a note; `None` parts are hidden
+#[derive(Clone, Copy)]
+struct NoteQuads {
+    head: Quad,
+    border: Quad,
+    hold_body: Option<Quad>,
+}
+
+/// Query over the chart entities keyed by `K` and drawing part `P`
+type PieceQuery<'w, 's, K, P> = Query<
+    'w,
+    's,
+    (
+        &'static K,
+        &'static P,
+        &'static mut Transform,
+        &'static mut Visibility,
+        &'static Handle<ColorMaterial>,
+    ),
+>;
+
+/// Line meshes, judge ring and note pieces; they all write `Visibility`, so they share a `ParamSet`
+type ChartPieces<'w, 's> = ParamSet<
+    'w,
+    's,
+    (
+        Query<
+            'static,
+            'static,
+            (
+                &'static LineMesh,
+                &'static Mesh2dHandle,
+                &'static mut Visibility,
+            ),
+        >,
+        PieceQuery<'static, 'static, JudgeRing, RingSide>,
+        PieceQuery<'static, 'static, NoteEntity, NotePart>,
+    ),
+>;
+
+/// Spawn the persistent entities of the chart, all hidden: the mesh of each line,
+/// the four sides of its judge ring and the parts of every note.
+/// Runs again whenever a new `GameState` is inserted, replacing the old ones.
+fn spawn_chart_entities(
     mut commands: Commands,
+    game_state: Res<GameState>,
+    handles: Res<RenderHandles>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    old_entities: Query<Entity, With<GameEntity>>,
+) {
+    for entity in old_entities.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+
+    for (line_index, line) in game_state.chart.lines.iter().enumerate() {
+        commands.spawn((
+            MaterialMesh2dBundle {
+                mesh: meshes.add(strip_mesh()).into(),
+                material: handles.white.clone(),
+                transform: Transform::from_xyz(0.0, 0.0, 1.0),
+                visibility: Visibility::Hidden,
+                ..default()
+            },
+            // The mesh is rebuilt every frame, so bounds computed at spawn are meaningless
+            NoFrustumCulling,
+            GameEntity,
+            LineMesh { line_index },
+        ));
+
+        // The four sides of a ring always share one color
+        let ring_material = materials.add(Color::WHITE);
+        for side in RingSide::ALL {
+            commands.spawn((
+                quad_bundle(&handles, ring_material.clone()),
+                GameEntity,
+                JudgeRing { line_index },
+                side,
+            ));
+        }
+
+        for (note_index, note) in line.notes.iter().enumerate() {
+            let key = NoteEntity {
+                line_index,
+                note_index,
+            };
+            // Notes of a type share one color each frame, so they share its material
+            let note_material = if note.note_type == 1 {
+                &handles.drag
+            } else {
+                &handles.note
+            };
+            commands.spawn((
+                quad_bundle(&handles, handles.black.clone()),
+                GameEntity,
+                key,
+                NotePart::Border,
+            ));
+            if note.note_type == 2 {
+                commands.spawn((
+                    quad_bundle(&handles, note_material.clone()),
+                    GameEntity,
+                    key,
+                    NotePart::HoldBody,
+                ));
+            }
+            commands.spawn((
+                quad_bundle(&handles, note_material.clone()),
+                GameEntity,
+                key,
+                NotePart::Head,
+            ));
+        }
+    }
+}
+
+pub(crate) fn quad_bundle(
+    handles: &RenderHandles,
+    material: Handle<ColorMaterial>,
+) -> MaterialMesh2dBundle<ColorMaterial> {
+    MaterialMesh2dBundle {
+        mesh: handles.quad.clone().into(),
+        material,
+        visibility: Visibility::Hidden,
+        ..default()
+    }
+}
+
+/// Main rendering update system.
+/// Builds the line meshes and computes every quad of the frame, then updates
+/// the chart entities in place.
+#[allow(clippy::too_many_arguments)]
+fn update_rendering(
     mut game_state: ResMut<GameState>,
+    mut strips: Local<Vec<StripBuilder>>,
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<ColorMaterial>>,
     mut note_states: ResMut<NoteStates>,
     mut hit_count: ResMut<HitCount>,
     mut background_query: Query<&mut Sprite, With<Background>>,
-    game_entities: Query<Entity, With<GameEntity>>,
+    mut pieces: ChartPieces,
+    playfield: Res<Playfield>,
 ) {
     // Update canvas states
     update_canvas_states(&mut game_state);
 
     let tick = game_state.current_tick();
-    let _timer = game_state.current_time;
     let scale = game_state.camera_scale() as f32;
-    let screen_width = WINDOW_WIDTH;
-    let screen_height = WINDOW_HEIGHT;
+    let screen_width = playfield.width;
+    let screen_height = playfield.height;
 
     // Update background color
     let bg_color = game_state.get_theme_color(0);
@@ -126,20 +431,29 @@ fn update_rendering(
         sprite.color = bg_color.to_bevy_color();
     }
 
-    // Clear old dynamic entities (we'll rebuild each frame for simplicity)
-    for entity in game_entities.iter() {
-        commands.entity(entity).despawn();
-    }
+    strips.resize_with(game_state.chart.lines.len(), StripBuilder::default);
+    let mut polyline = Vec::new();
+    let mut samples = Vec::new();
+    let line_width = LINE_WIDTH * scale;
+    let mut rings: Vec<Option<Quad>> = vec![None; game_state.chart.lines.len()];
 
-    // Draw lines
-    for (_line_idx, line) in game_state.chart.lines.iter().enumerate() {
+    // Lines
+    for (line_idx, line) in game_state.chart.lines.iter().enumerate() {
         let line_points = &line.line_points;
         let line_color = &line.line_color;
+        let strip = &mut strips[line_idx];
+        strip.clear();
+        polyline.clear();
 
-        for i in 0..line_points.len() {
+        for i in 0..line_points.len().saturating_sub(1) {
             let point = &line_points[i];
+            let next_point = &line_points[i + 1];
 
-            if point.canvas_index >= game_state.canvas_states.len() {
+            if point.canvas_index >= game_state.canvas_states.len()
+                || next_point.canvas_index >= game_state.canvas_states.len()
+            {
+                strip.add_polyline(&polyline, line_width);
+                polyline.clear();
                 continue;
             }
 
@@ -157,134 +471,111 @@ fn update_rendering(
                 screen_height as f64,
             );
 
-            // Draw line segment to next point
-            if i + 1 < line_points.len() {
-                let next_point = &line_points[i + 1];
-                if next_point.canvas_index >= game_state.canvas_states.len() {
-                    continue;
-                }
+            let next_canvas_state = &game_state.canvas_states[next_point.canvas_index];
+            let next_computed = compute_line_point(
+                &game_state,
+                next_point,
+                next_canvas_state.fp,
+                next_canvas_state.x as f32 as f64 * screen_width as f64,
+                line_color,
+                screen_width as f64,
+                screen_height as f64,
+            );
 
-                let next_canvas_state = &game_state.canvas_states[next_point.canvas_index];
-                let next_computed = compute_line_point(
-                    &game_state,
-                    next_point,
-                    next_canvas_state.fp,
-                    next_canvas_state.x as f32 as f64 * screen_width as f64,
-                    line_color,
-                    screen_width as f64,
-                    screen_height as f64,
-                );
-
-                // Skip if completely off screen
-                // If start is above top margin, whole segment is above
-                if computed.y > screen_height as f64 * 2.0 {
-                    continue;
-                }
-                // If end is below bottom margin, whole segment is below
-                if next_computed.y < -screen_height as f64 * 2.0 {
-                    continue;
+            // Judge ring if within time range
+            if tick >= point.time && tick < next_point.time {
+                let progress = (tick - point.time) / (next_point.time - point.time);
+                let ease_value = point.ease(progress as f32);
+                let ring_x = computed.x + ease_value as f64 * (next_computed.x - computed.x);
+
+                if let Some(ring_color) = get_current_judge_ring_color(&line.judge_ring_color, tick)
+                {
+                    let mixed_color = calculate_mixed_color(tick, &ring_color, line_color);
+                    rings[line_idx] = Some(Quad::new(
+                        Vec2::new(ring_x as f32, 0.0),
+                        Vec2::splat(30.0 * scale),
+                        2.0,
+                        mixed_color.to_bevy_color(),
+                    ));
                 }
+            }
 
-                // Draw line segment with easing
-                let steps = 10;
-                let mut prev_pos = Vec2::new(computed.x as f32, computed.y as f32);
-                let mut prev_color = computed.color.to_bevy_color();
-
-                for s in 1..=steps {
-                    let t = s as f32 / steps as f32;
-                    let ease_val = apply_ease(point.ease_type, t) as f64;
-                    
-                    let current_time = point.time + (next_point.time - point.time) * t as f64;
-                    let current_chart_x = point.x_position + (next_point.x_position - point.x_position) * ease_val;
-                    
-                    let current_seconds = tick_to_seconds(current_time, &game_state.chart.bpm_shifts, game_state.chart.bpm);
-                    let current_fp = speed_to_fp(
-                        current_seconds,
-                        &game_state.chart.canvas_moves[point.canvas_index].speed_key_points,
-                        &game_state.chart.bpm_shifts,
-                        game_state.chart.bpm,
-                    );
-                    
-                    let current_screen_x = current_chart_x * scale as f64 * screen_width as f64 + canvas_x as f64;
-                    let current_screen_y = (current_fp - canvas_fp) * screen_height as f64 * game_state.speed * scale as f64;
-                    
-                    let current_pos = Vec2::new(current_screen_x as f32, current_screen_y as f32);
-                    let mixed_color = calculate_mixed_color(current_time, &point.color, line_color).to_bevy_color();
-
-                    let dx = current_pos.x - prev_pos.x;
-                    let dy = current_pos.y - prev_pos.y;
-                    let length = (dx * dx + dy * dy).sqrt();
-                    
-                    if length >= 0.1 {
-                        let angle = dy.atan2(dx);
-                        let mid_x = (prev_pos.x + current_pos.x) / 2.0;
-                        let mid_y = (prev_pos.y + current_pos.y) / 2.0;
-                        
-                        commands.spawn((
-                            MaterialMesh2dBundle {
-                                mesh: meshes.add(Rectangle::new(length, 3.0 * scale)).into(),
-                                material: materials.add(prev_color),
-                                transform: Transform::from_xyz(mid_x, mid_y, 1.0)
-                                    .with_rotation(Quat::from_rotation_z(angle)),
-                                ..default()
-                            },
-                            GameEntity,
-                        ));
-                    }
-                    
-                    prev_pos = current_pos;
-                    prev_color = mixed_color;
-                }
+            // Cull the whole segment when off screen:
+            // if start is above top margin, whole segment is above;
+            // if end is below bottom margin, whole segment is below
+            if computed.y > screen_height as f64 * 2.0
+                || next_computed.y < -screen_height as f64 * 2.0
+            {
+                strip.add_polyline(&polyline, line_width);
+                polyline.clear();
+                continue;
             }
 
-            // Draw judge ring if within time range
-            if tick >= point.time {
-                if i + 1 < line_points.len() {
-                    let next_point = &line_points[i + 1];
-                    if tick < next_point.time {
-                        let progress = (tick - point.time) / (next_point.time - point.time);
-                        let ease_value = apply_ease(point.ease_type, progress as f32);
-
-                        let next_canvas_state = &game_state.canvas_states[next_point.canvas_index];
-                        let next_computed = compute_line_point(
-                            &game_state,
-                            next_point,
-                            next_canvas_state.fp,
-                            next_canvas_state.x as f32 as f64 * screen_width as f64,
-                            line_color,
-                            screen_width as f64,
-                            screen_height as f64,
-                        );
-
-                        let ring_x = computed.x + ease_value as f64 * (next_computed.x - computed.x);
-
-                        if let Some(ring_color) = get_current_judge_ring_color(&line.judge_ring_color, tick) {
-                            let mixed_color = calculate_mixed_color(tick, &ring_color, line_color);
-                            draw_judge_ring(
-                                &mut commands,
-                                &mut meshes,
-                                &mut materials,
-                                ring_x as f32,
-                                0.0,
-                                30.0 * scale,
-                                mixed_color,
-                            );
-                        }
-                    }
-                }
+            // Sample the eased segment, more densely where it bends
+            let segment_position = |t: f32| {
+                let ease_val = point.ease(t) as f64;
+                let current_time = point.time + (next_point.time - point.time) * t as f64;
+                let current_chart_x =
+                    point.x_position + (next_point.x_position - point.x_position) * ease_val;
+
+                let current_seconds = game_state.timing.tick_to_seconds(current_time);
+                let current_fp = game_state.canvas_fp(point.canvas_index, current_seconds);
+
+                let current_screen_x =
+                    current_chart_x * scale as f64 * screen_width as f64 + canvas_x as f64;
+                let current_screen_y = (current_fp - canvas_fp)
+                    * screen_height as f64
+                    * game_state.speed
+                    * scale as f64;
+                Vec2::new(current_screen_x as f32, current_screen_y as f32)
+            };
+            // Symmetric in-out eases cross the chord at the midpoint, so
+            // eased segments are always split a couple of times
+            let min_depth = if point.is_linear() { 0 } else { 2 };
+            samples.clear();
+            subdivide_curve(segment_position, min_depth, &mut samples);
+
+            // Continue the current polyline unless the line jumps
+            let continues = polyline.last().is_some_and(|last: &PolylinePoint| {
+                last.position.distance(samples[0].1) < LINE_BREAK_DISTANCE
+            });
+            if !continues {
+                strip.add_polyline(&polyline, line_width);
+                polyline.clear();
             }
+            let skip = usize::from(continues);
+            polyline.extend(
+                samples
+                    .iter()
+                    .skip(skip)
+                    .map(|&(t, position)| PolylinePoint {
+                        position,
+                        color: computed.color.lerp(&next_computed.color, t).to_bevy_color(),
+                    }),
+            );
         }
+
+        strip.add_polyline(&polyline, line_width);
     }
 
     // Initialize note states if needed
     if note_states.states.len() != game_state.chart.lines.len() {
         note_states.states.clear();
         for line in &game_state.chart.lines {
-            note_states.states.push(vec![NoteState::default(); line.notes.len()]);
+            note_states
+                .states
+                .push(vec![NoteState::default(); line.notes.len()]);
         }
     }
 
-    // Draw notes
+    let mut notes: Vec<Vec<Option<NoteQuads>>> = game_state
+        .chart
+        .lines
+        .iter()
+        .map(|line| vec![None; line.notes.len()])
+        .collect();
+
+    // Notes
     for (line_idx, line) in game_state.chart.lines.iter().enumerate() {
         let line_points = &line.line_points;
 
@@ -300,6 +591,24 @@ fn update_rendering(
                 continue;
             }
 
+            // In play mode the judge decides what is hit
+            if game_state.play_mode == PlayMode::Autoplay {
+                // Check if note should be hit
+                if !note_state.is_hit && tick >= note.time {
+                    note_state.is_hit = true;
+                    note_state.is_play_hit = true;
+                    note_state.result = Some(autoplay_result(&game_state, note.time));
+                    hit_count.0 += 1;
+                }
+
+                // Reset if going backward in time
+                if tick < note.time {
+                    note_state.is_hit = false;
+                    note_state.is_play_hit = false;
+                    note_state.result = None;
+                }
+            }
+
             // Find the line point for this note's time
             let (point, next_point) = find_line_points_for_time(line_points, note.time);
 
@@ -310,17 +619,29 @@ fn update_rendering(
             let canvas_state = &game_state.canvas_states[point.canvas_index];
 
             // Calculate note position
-            let note_fp = speed_to_fp(
-                tick_to_seconds(note.time, &game_state.chart.bpm_shifts, game_state.chart.bpm),
-                &game_state.chart.canvas_moves[point.canvas_index].speed_key_points,
-                &game_state.chart.bpm_shifts,
-                game_state.chart.bpm,
+            let note_fp = game_state.canvas_fp(
+                point.canvas_index,
+                game_state.timing.tick_to_seconds(note.time),
             );
 
-            let point_x = point.x_position * scale as f64 * screen_width as f64 + canvas_state.x * screen_width as f64;
+            let note_y = if note.note_type == 2 && tick >= note.time {
+                0.0
+            } else {
+                (note_fp - canvas_state.fp) * screen_height as f64 * game_state.speed * scale as f64
+            };
+
+            // Cull notes above the top margin; hold bodies extend further up
+            if note_y > screen_height as f64 * 2.0 {
+                continue;
+            }
+
+            let point_x = point.x_position * scale as f64 * screen_width as f64
+                + canvas_state.x * screen_width as f64;
             let next_point_x = if let Some(np) = next_point {
-                let next_canvas = &game_state.canvas_states[np.canvas_index.min(game_state.canvas_states.len() - 1)];
-                np.x_position * scale as f64 * screen_width as f64 + next_canvas.x * screen_width as f64
+                let next_canvas = &game_state.canvas_states
+                    [np.canvas_index.min(game_state.canvas_states.len() - 1)];
+                np.x_position * scale as f64 * screen_width as f64
+                    + next_canvas.x * screen_width as f64
             } else {
                 point_x
             };
@@ -335,90 +656,123 @@ fn update_rendering(
                 0.0
             };
 
-            let ease_value = apply_ease(point.ease_type, t as f32) as f64;
+            let ease_value = point.ease(t as f32) as f64;
             let note_x = point_x + ease_value * (next_point_x - point_x);
 
-            let note_y = if note.note_type == 2 && tick >= note.time {
-                0.0
-            } else {
-                (note_fp - canvas_state.fp) * screen_height as f64 * game_state.speed * scale as f64
-            };
-
-            // Check if note should be hit
-            if !note_state.is_hit && tick >= note.time {
-                note_state.is_hit = true;
-                note_state.is_play_hit = true;
-                hit_count.0 += 1;
-            }
-
-            // Reset if going backward in time
-            if tick < note.time {
-                note_state.is_hit = false;
-                note_state.is_play_hit = false;
-            }
-
             // Get note color
             let note_color = if note.note_type == 1 {
                 WHITE_COLOR
             } else {
                 game_state.get_theme_color(1)
-            };
+            }
+            .to_bevy_color();
 
-            // Draw note
+            let note_pos = Vec2::new(note_x as f32, note_y as f32);
             let note_size = 20.0 * scale;
-            draw_note(
-                &mut commands,
-                &mut meshes,
-                &mut materials,
-                note_x as f32,
-                note_y as f32,
-                note_size,
-                note_color,
-            );
 
-            // Draw hold body if hold note
-            if note.note_type == 2 && !note.other_informations.is_empty() {
-                let end_time = note.other_informations[0];
-                if tick <= end_time {
-                    let end_canvas_idx = if note.other_informations.len() > 1 {
-                        note.other_informations[1] as usize
-                    } else {
-                        point.canvas_index
-                    };
-
-                    if end_canvas_idx < game_state.canvas_states.len() {
-                        let end_fp = speed_to_fp(
-                            tick_to_seconds(end_time, &game_state.chart.bpm_shifts, game_state.chart.bpm),
-                            &game_state.chart.canvas_moves[end_canvas_idx].speed_key_points,
-                            &game_state.chart.bpm_shifts,
-                            game_state.chart.bpm,
-                        );
-
-                        let end_canvas = &game_state.canvas_states[end_canvas_idx];
-                        let end_y = (end_fp - end_canvas.fp) * screen_height as f64 * game_state.speed * scale as f64;
-                        let height = (end_y - note_y) as f32;
-
-                        draw_hold_body(
-                            &mut commands,
-                            &mut meshes,
-                            &mut materials,
-                            note_x as f32,
-                            note_y as f32,
-                            10.0 * scale,
-                            height,
-                            note_color,
-                        );
-                    }
+            // Hold body if hold note
+            let mut hold_body = None;
+            let end_time = note.other_informations.first().copied();
+            if let Some(end_time) =
+                end_time.filter(|&end_time| note.note_type == 2 && tick <= end_time)
+            {
+                let end_canvas_idx = if note.other_informations.len() > 1 {
+                    note.other_informations[1] as usize
+                } else {
+                    point.canvas_index
+                };
+
+                if end_canvas_idx < game_state.canvas_states.len() {
+                    let end_fp = game_state
+                        .canvas_fp(end_canvas_idx, game_state.timing.tick_to_seconds(end_time));
+
+                    let end_canvas = &game_state.canvas_states[end_canvas_idx];
+                    let end_y = (end_fp - end_canvas.fp)
+                        * screen_height as f64
+                        * game_state.speed
+                        * scale as f64;
+                    let height = (end_y - note_y) as f32;
+
+                    hold_body = Some(Quad::new(
+                        note_pos + Vec2::new(0.0, height / 2.0),
+                        Vec2::new(10.0 * scale, height.abs()),
+                        2.5,
+                        note_color,
+                    ));
                 }
             }
+
+            notes[line_idx][note_idx] = Some(NoteQuads {
+                head: Quad::new(note_pos, Vec2::splat(note_size), 3.0, note_color),
+                border: Quad::new(note_pos, Vec2::splat(note_size + 3.0), 2.9, Color::BLACK),
+                hold_body,
+            });
         }
     }
+
+    for (key, mesh, mut visibility) in pieces.p0().iter_mut() {
+        match strips.get(key.line_index).filter(|strip| !strip.is_empty()) {
+            Some(strip) => {
+                visibility.set_if_neq(Visibility::Inherited);
+                if let Some(mesh) = meshes.get_mut(&mesh.0) {
+                    strip.write_to(mesh);
+                }
+            }
+            None => {
+                visibility.set_if_neq(Visibility::Hidden);
+            }
+        }
+    }
+
+    for (key, side, mut transform, mut visibility, material) in pieces.p1().iter_mut() {
+        let quad = rings
+            .get(key.line_index)
+            .copied()
+            .flatten()
+            .map(|ring| ring_side(ring, *side, JUDGE_RING_LINE_WIDTH));
+        place_quad(
+            quad,
+            &mut transform,
+            &mut visibility,
+            material,
+            &mut materials,
+        );
+    }
+
+    for (key, part, mut transform, mut visibility, material) in pieces.p2().iter_mut() {
+        let quads = notes
+            .get(key.line_index)
+            .and_then(|line| line.get(key.note_index))
+            .copied()
+            .flatten();
+        let quad = quads.and_then(|quads| match part {
+            NotePart::Head => Some(quads.head),
+            NotePart::Border => Some(quads.border),
+            NotePart::HoldBody => quads.hold_body,
+        });
+        place_quad(
+            quad,
+            &mut transform,
+            &mut visibility,
+            material,
+            &mut materials,
+        );
+    }
 }
 
-fn find_line_points_for_time<'a>(
-    points: &'a [crate::chart::LinePoint],
+/// Perfect hit exactly on the note
+fn autoplay_result(game_state: &GameState, note_tick: f64) -> NoteResult {
+    NoteResult {
+        judgement: Judgement::Perfect,
+        time: game_state.timing.tick_to_seconds(note_tick),
+        offset: 0.0,
+    }
+}
+
+fn find_line_points_for_time(
+    points: &[crate::chart::LinePoint],
     time: f64,
-) -> (&'a crate::chart::LinePoint, Option<&'a crate::chart::LinePoint>) {
+) -> (&crate::chart::LinePoint, Option<&crate::chart::LinePoint>) {
     let mut left = 0;
     let mut right = points.len().saturating_sub(1);
     let mut target_index = right;
@@ -446,119 +800,54 @@ fn find_line_points_for_time<'a>(
     (point, next_point)
 }
 
-
-fn draw_judge_ring(
-    commands: &mut Commands,
-    meshes: &mut ResMut<Assets<Mesh>>,
-    materials: &mut ResMut<Assets<ColorMaterial>>,
-    x: f32,
-    y: f32,
-    size: f32,
-    color: ChartColor,
-) {
-    let bevy_color = color.to_bevy_color();
-
-    // Draw ring as 4 rectangles (top, bottom, left, right)
-    let half_size = size / 2.0;
-    let line_width = JUDGE_RING_LINE_WIDTH;
-
-    // Top
-    commands.spawn((
-        MaterialMesh2dBundle {
-            mesh: meshes.add(Rectangle::new(size, line_width)).into(),
-            material: materials.add(bevy_color),
-            transform: Transform::from_xyz(x, y + half_size, 2.0),
-            ..default()
-        },
-        GameEntity,
-    ));
-
-    // Bottom
-    commands.spawn((
-        MaterialMesh2dBundle {
-            mesh: meshes.add(Rectangle::new(size, line_width)).into(),
-            material: materials.add(bevy_color),
-            transform: Transform::from_xyz(x, y - half_size, 2.0),
-            ..default()
-        },
-        GameEntity,
-    ));
-
-    // Left
-    commands.spawn((
-        MaterialMesh2dBundle {
-            mesh: meshes.add(Rectangle::new(line_width, size)).into(),
-            material: materials.add(bevy_color),
-            transform: Transform::from_xyz(x - half_size, y, 2.0),
-            ..default()
-        },
-        GameEntity,
-    ));
-
-    // Right
-    commands.spawn((
-        MaterialMesh2dBundle {
-            mesh: meshes.add(Rectangle::new(line_width, size)).into(),
-            material: materials.add(bevy_color),
-            transform: Transform::from_xyz(x + half_size, y, 2.0),
-            ..default()
-        },
-        GameEntity,
-    ));
-}
-
-fn draw_note(
-    commands: &mut Commands,
-    meshes: &mut ResMut<Assets<Mesh>>,
-    materials: &mut ResMut<Assets<ColorMaterial>>,
-    x: f32,
-    y: f32,
-    size: f32,
-    color: ChartColor,
-) {
-    let bevy_color = color.to_bevy_color();
-
-    commands.spawn((
-        MaterialMesh2dBundle {
-            mesh: meshes.add(Rectangle::new(size, size)).into(),
-            material: materials.add(bevy_color),
-            transform: Transform::from_xyz(x, y, 3.0),
-            ..default()
-        },
-        GameEntity,
-    ));
-
-    // Draw border
-    commands.spawn((
-        MaterialMesh2dBundle {
-            mesh: meshes.add(Rectangle::new(size + 3.0, size + 3.0)).into(),
-            material: materials.add(Color::BLACK),
-            transform: Transform::from_xyz(x, y, 2.9),
-            ..default()
-        },
-        GameEntity,
-    ));
+/// One side of a `line_width` thick square outline of `ring`
+pub(crate) fn ring_side(ring: Quad, side: RingSide, line_width: f32) -> Quad {
+    let half_size = ring.size / 2.0;
+    let (offset, size) = match side {
+        RingSide::Top => (
+            Vec2::new(0.0, half_size.y),
+            Vec2::new(ring.size.x, line_width),
+        ),
+        RingSide::Bottom => (
+            Vec2::new(0.0, -half_size.y),
+            Vec2::new(ring.size.x, line_width),
+        ),
+        RingSide::Left => (
+            Vec2::new(-half_size.x, 0.0),
+            Vec2::new(line_width, ring.size.y),
+        ),
+        RingSide::Right => (
+            Vec2::new(half_size.x, 0.0),
+            Vec2::new(line_width, ring.size.y),
+        ),
+    };
+    Quad::new(ring.center + offset, size, ring.z, ring.color)
 }
 
-fn draw_hold_body(
-    commands: &mut Commands,
-    meshes: &mut ResMut<Assets<Mesh>>,
-    materials: &mut ResMut<Assets<ColorMaterial>>,
-    x: f32,
-    y: f32,
-    width: f32,
-    height: f32,
-    color: ChartColor,
+/// Move an entity onto `quad`, or hide it. Only touches what changed,
+/// so static and hidden entities don't trigger transform or asset updates.
+pub(crate) fn place_quad(
+    quad: Option<Quad>,
+    transform: &mut Mut<Transform>,
+    visibility: &mut Mut<Visibility>,
+    material: &Handle<ColorMaterial>,
+    materials: &mut Assets<ColorMaterial>,
 ) {
-    let bevy_color = color.to_bevy_color();
+    let Some(quad) = quad else {
+        visibility.set_if_neq(Visibility::Hidden);
+        return;
+    };
 
-    commands.spawn((
-        MaterialMesh2dBundle {
-            mesh: meshes.add(Rectangle::new(width, height.abs())).into(),
-            material: materials.add(bevy_color),
-            transform: Transform::from_xyz(x, y + height / 2.0, 2.5),
-            ..default()
-        },
-        GameEntity,
-    ));
+    visibility.set_if_neq(Visibility::Inherited);
+    transform.set_if_neq(
+        Transform::from_translation(quad.center.extend(quad.z)).with_scale(quad.size.extend(1.0)),
+    );
+    if materials
+        .get(material)
+        .is_some_and(|m| m.color != quad.color)
+    {
+        if let Some(m) = materials.get_mut(material) {
+            m.color = quad.color;
+        }
+    }
 }
@@ -0,0 +1,204 @@
+//! Replays: the timestamped input of a play session, saved as JSON.
+//!
+//! The judge depends only on the chart and the input events, so feeding a
+//! replay's events back through it reproduces the session's judgements and
+//! score. A replay names its chart by a hash of the chart file, and is
+//! rejected for any other chart.
+
+use std::fmt;
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::chart::Chart;
+use crate::judge::{InputEvent, Judge, JudgeWindows};
+
+/// Replay format written by this version
+pub const REPLAY_VERSION: u32 = 1;
+
+/// Errors raised while loading, saving or checking a replay
+#[derive(Debug)]
+pub enum ReplayError {
+    Io(std::io::Error),
+    Json(serde_json::Error),
+    UnsupportedVersion(u32),
+    ChartMismatch { expected: String, found: String },
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReplayError::Io(e) => write!(f, "cannot access replay file: {}", e),
+            ReplayError::Json(e) => write!(f, "invalid replay file: {}", e),
+            ReplayError::UnsupportedVersion(version) => {
+                write!(f, "unsupported replay version {}", version)
+            }
+            ReplayError::ChartMismatch { expected, found } => {
+                write!(
+                    f,
+                    "replay was recorded on chart {} but this chart is {}",
+                    expected, found
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for ReplayError {}
+
+/// Input of one play session
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Replay {
+    pub version: u32,
+    /// `chart_hash` of the chart file played
+    pub chart_hash: String,
+    pub songs_name: String,
+    pub windows: JudgeWindows,
+    /// Start of the session in seconds; earlier notes are skipped
+    pub start: f64,
+    /// Input events in time order
+    pub events: Vec<InputEvent>,
+}
+
+impl Replay {
+    pub fn new(chart: &Chart, chart_hash: String, windows: JudgeWindows, start: f64) -> Self {
+        Self {
+            version: REPLAY_VERSION,
+            chart_hash,
+            songs_name: chart.songs_name.clone(),
+            windows,
+            start,
+            events: Vec::new(),
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Self, ReplayError> {
+        let reader = BufReader::new(File::open(path).map_err(ReplayError::Io)?);
+        let replay: Replay = serde_json::from_reader(reader).map_err(ReplayError::Json)?;
+        if replay.version != REPLAY_VERSION {
+            return Err(ReplayError::UnsupportedVersion(replay.version));
+        }
+        Ok(replay)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), ReplayError> {
+        let writer = BufWriter::new(File::create(path).map_err(ReplayError::Io)?);
+        serde_json::to_writer(writer, self).map_err(ReplayError::Json)
+    }
+
+    /// Reject the replay unless it was recorded on the chart hashed to `chart_hash`
+    pub fn check_chart(&self, chart_hash: &str) -> Result<(), ReplayError> {
+        if self.chart_hash == chart_hash {
+            Ok(())
+        } else {
+            Err(ReplayError::ChartMismatch {
+                expected: self.chart_hash.clone(),
+                found: chart_hash.to_string(),
+            })
+        }
+    }
+
+    /// Judge the whole replay on `chart`; notes never hit end up missed
+    pub fn judge(&self, chart: &Chart) -> Judge {
+        let mut judge = Judge::new(chart, self.windows, self.start);
+        for event in &self.events {
+            judge.input(event);
+        }
+        judge.advance(f64::INFINITY);
+        judge
+    }
+}
+
+/// Hash of a chart file's content, as 16 hex digits (64-bit FNV-1a)
+pub fn chart_hash(content: &[u8]) -> String {
+    let hash = content
+        .iter()
+        .fold(0xcbf2_9ce4_8422_2325_u64, |hash, &byte| {
+            (hash ^ byte as u64).wrapping_mul(0x0000_0100_0000_01b3)
+        });
+    format!("{:016x}", hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::chart::tests::test_chart;
+    use crate::judge::{InputAction, InputSource};
+    use crate::score::ScoreModel;
+
+    /// Frame rate at which the player's judge is fed
+    const FPS: f64 = 30.0;
+
+    fn event(time: f64, action: InputAction, key: &str) -> InputEvent {
+        InputEvent {
+            time,
+            action,
+            source: InputSource::Key(key.into()),
+            x: None,
+        }
+    }
+
+    #[test]
+    fn replay_reproduces_the_score_judged_frame_by_frame() {
+        let chart = test_chart(json!([
+            { "type": 0, "time": 1.0, "floorPosition": 0.0, "otherInformations": [] },
+            { "type": 0, "time": 1.5, "floorPosition": 0.0, "otherInformations": [] },
+            { "type": 1, "time": 2.0, "floorPosition": 0.0, "otherInformations": [] },
+            { "type": 2, "time": 2.5, "floorPosition": 0.0, "otherInformations": [3.5] },
+            { "type": 0, "time": 4.0, "floorPosition": 0.0, "otherInformations": [] },
+            { "type": 0, "time": 4.2, "floorPosition": 0.0, "otherInformations": [] }
+        ]));
+        let mut replay = Replay::new(&chart, chart_hash(b"chart"), JudgeWindows::default(), 0.0);
+        replay.events = vec![
+            event(1.01, InputAction::Down, "A"),
+            event(1.1, InputAction::Up, "A"),
+            event(1.56, InputAction::Down, "S"),
+            event(1.9, InputAction::Down, "D"),
+            event(2.05, InputAction::Up, "D"),
+            event(2.1, InputAction::Up, "S"),
+            event(2.52, InputAction::Down, "A"),
+            event(3.0, InputAction::Up, "A"),
+            event(4.25, InputAction::Down, "F"),
+            event(4.3, InputAction::Up, "F"),
+        ];
+
+        // As play.rs does: each frame judges the input it saw, then advances to the frame time
+        let mut judge = Judge::new(&chart, replay.windows, replay.start);
+        let mut events = replay.events.iter().peekable();
+        for frame in 0..(6.0 * FPS) as usize {
+            let time = frame as f64 / FPS;
+            while let Some(event) = events.next_if(|event| event.time <= time) {
+                judge.input(event);
+            }
+            judge.advance(time);
+        }
+
+        let model = ScoreModel::new(&chart);
+        let played = model.summarize_judge(&judge);
+        let replayed = model.summarize_judge(&replay.judge(&chart));
+        assert_eq!(replayed, played);
+        assert_eq!(played.judged, 6);
+        assert!(played.perfect > 0 && played.good > 0 && played.miss > 0);
+    }
+
+    #[test]
+    fn replay_of_another_chart_is_rejected() {
+        let chart = test_chart(json!([]));
+        let replay = Replay::new(
+            &chart,
+            chart_hash(b"recorded chart"),
+            JudgeWindows::default(),
+            0.0,
+        );
+        assert!(replay.check_chart(&chart_hash(b"recorded chart")).is_ok());
+        assert!(matches!(
+            replay.check_chart(&chart_hash(b"edited chart")),
+            Err(ReplayError::ChartMismatch { .. })
+        ));
+    }
+}
@@ -0,0 +1,217 @@
+//! Running score and the results screen shown once the song is over.
+//!
+//! The score is summarized every frame from the note judgements, so the HUD
+//! combo breaks on misses. When the song ends the results are drawn over the
+//! chart and, if asked for, written as JSON for other tools.
+
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+
+use bevy::prelude::*;
+use bevy::window::PrimaryWindow;
+
+use crate::game::{chart_end_time, GameState};
+use crate::rendering::{
+    place_quad, quad_bundle, GameEntity, NoteStates, Playfield, Quad, RenderHandles,
+};
+use crate::score::{ScoreModel, ScoreSummary};
+
+/// Depth of the results screen, above the HUD
+const RESULTS_Z: f32 = 11.0;
+
+/// Wait after the last note before showing the results when the song length is unknown
+pub const RESULTS_DELAY: f64 = 1.0;
+
+/// Results settings
+#[derive(Resource, Default, Clone)]
+pub struct ResultsConfig {
+    /// End of the song in seconds; defaults to `RESULTS_DELAY` after the last note
+    pub song_end: Option<f64>,
+    /// File the results are written to as JSON when the song ends
+    pub output: Option<PathBuf>,
+}
+
+/// Score of the judgements so far
+#[derive(Resource, Default)]
+pub struct Score(pub ScoreSummary);
+
+/// Part of the results screen
+#[derive(Component, Clone, Copy, PartialEq)]
+pub enum ResultsPanel {
+    Backdrop,
+    /// Sections are the title, score, accuracy, max combo and judgement counts
+    Text,
+}
+
+/// Time from which the results are shown, and whether they were written already
+#[derive(Default)]
+pub struct ResultsProgress {
+    end_time: f64,
+    written: bool,
+}
+
+/// Rebuild the score model of a newly inserted chart and spawn the hidden results screen
+pub fn spawn_results(
+    mut commands: Commands,
+    game_state: Res<GameState>,
+    handles: Res<RenderHandles>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    commands.insert_resource(ScoreModel::new(&game_state.chart));
+
+    commands.spawn((
+        quad_bundle(&handles, materials.add(Color::NONE)),
+        GameEntity,
+        ResultsPanel::Backdrop,
+    ));
+
+    let style = TextStyle {
+        color: Color::BLACK,
+        ..default()
+    };
+    let text = Text::from_sections((0..5).map(|_| TextSection::new("", style.clone())))
+        .with_justify(JustifyText::Center);
+    commands.spawn((
+        Text2dBundle {
+            text,
+            visibility: Visibility::Hidden,
+            ..default()
+        },
+        GameEntity,
+        ResultsPanel::Text,
+    ));
+}
+
+/// Summarize the judgements so far
+pub fn update_score(
+    model: Option<Res<ScoreModel>>,
+    note_states: Res<NoteStates>,
+    mut score: ResMut<Score>,
+) {
+    let summary = model
+        .map(|model| model.summarize(&note_states.states))
+        .unwrap_or_default();
+    if score.0 != summary {
+        score.0 = summary;
+    }
+}
+
+/// Show the results once the song is over, writing them out the first time
+#[allow(clippy::too_many_arguments)]
+pub fn update_results(
+    game_state: Res<GameState>,
+    score: Res<Score>,
+    config: Res<ResultsConfig>,
+    playfield: Res<Playfield>,
+    window: Query<&Window, With<PrimaryWindow>>,
+    mut progress: Local<ResultsProgress>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut backdrops: Query<
+        (
+            &ResultsPanel,
+            &mut Transform,
+            &mut Visibility,
+            &Handle<ColorMaterial>,
+        ),
+        Without<Text>,
+    >,
+    mut texts: Query<(&mut Text, &mut Transform, &mut Visibility), With<ResultsPanel>>,
+) {
+    // A new chart starts over
+    if game_state.is_added() {
+        *progress = ResultsProgress {
+            end_time: config
+                .song_end
+                .unwrap_or_else(|| chart_end_time(&game_state.chart) + RESULTS_DELAY),
+            written: false,
+        };
+    }
+
+    let finished = game_state.current_time >= progress.end_time;
+    if !finished {
+        // Seeking back before the end plays the song out again
+        progress.written = false;
+    } else if !progress.written {
+        progress.written = true;
+        if let Some(path) = &config.output {
+            match write_results(path, &score.0) {
+                Ok(()) => println!("Wrote results to {}", path.display()),
+                Err(e) => eprintln!("Failed to write results to {}: {}", path.display(), e),
+            }
+        }
+    }
+
+    let view = match window.get_single() {
+        Ok(window) => Vec2::new(window.width(), window.height()),
+        Err(_) => Vec2::new(playfield.width, playfield.height),
+    };
+    // Same scale as the HUD
+    let kx = view.x / 360.0;
+    let center = Vec2::new(0.0, playfield.camera_offset());
+
+    let backdrop = Quad::new(
+        center,
+        view * 2.0,
+        RESULTS_Z,
+        game_state
+            .get_theme_color(0)
+            .to_bevy_color()
+            .with_alpha(0.9),
+    );
+    for (_, mut transform, mut visibility, material) in backdrops.iter_mut() {
+        place_quad(
+            finished.then_some(backdrop),
+            &mut transform,
+            &mut visibility,
+            material,
+            &mut materials,
+        );
+    }
+
+    for (mut text, mut transform, mut visibility) in texts.iter_mut() {
+        visibility.set_if_neq(if finished {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        });
+        if !finished {
+            continue;
+        }
+
+        let summary = &score.0;
+        let lines = [
+            (format!("{}\n", summary.songs_name), 16.0),
+            (format!("{:07}\n", summary.score), 40.0),
+            (format!("Accuracy {:.2}%\n", summary.accuracy), 14.0),
+            (format!("Max combo {}\n", summary.max_combo), 14.0),
+            (
+                format!(
+                    "Perfect {}  Good {}  Miss {}",
+                    summary.perfect, summary.good, summary.miss
+                ),
+                14.0,
+            ),
+        ];
+        let changed = text
+            .sections
+            .iter()
+            .zip(&lines)
+            .any(|(section, (value, size))| {
+                section.value != *value || section.style.font_size != size * kx
+            });
+        if changed {
+            for (section, (value, size)) in text.sections.iter_mut().zip(lines) {
+                section.value = value;
+                section.style.font_size = size * kx;
+            }
+        }
+        transform.set_if_neq(Transform::from_translation(center.extend(RESULTS_Z + 0.1)));
+    }
+}
+
+fn write_results(path: &Path, summary: &ScoreSummary) -> std::io::Result<()> {
+    let writer = BufWriter::new(File::create(path)?);
+    serde_json::to_writer_pretty(writer, summary)?;
+    Ok(())
+}
@@ -0,0 +1,120 @@
+//! Chart revelation view: the camera is zoomed out by `GameState::revelation_size`,
+//! the real screen is outlined in red and every canvas is labelled with its
+//! index at its x position, like `revelationSize` in the JS player.
+
+use bevy::prelude::*;
+
+use crate::game::GameState;
+use crate::rendering::{
+    place_quad, quad_bundle, ring_side, GameEntity, Playfield, Quad, RenderHandles, RingSide,
+};
+
+/// Depth of the overlay, above the chart and below the HUD
+const REVELATION_Z: f32 = 9.0;
+
+/// Width of the screen bounds outline
+const SCREEN_BOUNDS_LINE_WIDTH: f32 = 2.0;
+
+/// Side of the outline of the real screen
+#[derive(Component, Clone, Copy)]
+pub struct ScreenBounds(pub RingSide);
+
+/// Index label of a canvas
+#[derive(Component, Clone, Copy)]
+pub struct CanvasLabel {
+    pub canvas_index: usize,
+}
+
+/// Spawn the hidden overlay of a newly inserted chart
+pub fn spawn_revelation_overlay(
+    mut commands: Commands,
+    game_state: Res<GameState>,
+    handles: Res<RenderHandles>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    let red = materials.add(Color::srgb(1.0, 0.0, 0.0));
+    for side in RingSide::ALL {
+        commands.spawn((
+            quad_bundle(&handles, red.clone()),
+            GameEntity,
+            ScreenBounds(side),
+        ));
+    }
+
+    for canvas_index in 0..game_state.chart.canvas_moves.len() {
+        commands.spawn((
+            Text2dBundle {
+                text: Text::from_section(
+                    canvas_index.to_string(),
+                    TextStyle {
+                        color: Color::BLACK,
+                        ..default()
+                    },
+                ),
+                visibility: Visibility::Hidden,
+                ..default()
+            },
+            GameEntity,
+            CanvasLabel { canvas_index },
+        ));
+    }
+}
+
+/// Show the overlay while the revelation zoom is active
+pub fn update_revelation_overlay(
+    game_state: Res<GameState>,
+    playfield: Res<Playfield>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut bounds: Query<
+        (
+            &ScreenBounds,
+            &mut Transform,
+            &mut Visibility,
+            &Handle<ColorMaterial>,
+        ),
+        Without<CanvasLabel>,
+    >,
+    mut labels: Query<
+        (&CanvasLabel, &mut Text, &mut Transform, &mut Visibility),
+        Without<ScreenBounds>,
+    >,
+) {
+    let revelation = game_state.is_revelation();
+    let size = game_state.revelation_size as f32;
+    let scale = game_state.camera_scale() as f32;
+
+    // The screen shrinks around the judge line like the chart does
+    let screen = Quad::new(
+        Vec2::new(0.0, playfield.camera_offset() * size),
+        Vec2::new(playfield.width, playfield.height) * size,
+        REVELATION_Z,
+        Color::srgb(1.0, 0.0, 0.0),
+    );
+    for (ScreenBounds(side), mut transform, mut visibility, material) in bounds.iter_mut() {
+        let quad = revelation.then(|| ring_side(screen, *side, SCREEN_BOUNDS_LINE_WIDTH));
+        place_quad(
+            quad,
+            &mut transform,
+            &mut visibility,
+            material,
+            &mut materials,
+        );
+    }
+
+    for (label, mut text, mut transform, mut visibility) in labels.iter_mut() {
+        let canvas = game_state.canvas_states.get(label.canvas_index);
+        let Some(canvas) = canvas.filter(|_| revelation) else {
+            visibility.set_if_neq(Visibility::Hidden);
+            continue;
+        };
+
+        visibility.set_if_neq(Visibility::Inherited);
+        let x = canvas.x as f32 * playfield.width;
+        transform.set_if_neq(Transform::from_xyz(x, -20.0, REVELATION_Z));
+        // Same size as the JS `20 * cameraScale * (cvs.width / 360)`
+        let font_size = (20.0 * scale * playfield.width / 360.0).max(1.0);
+        if text.sections[0].style.font_size != font_size {
+            text.sections[0].style.font_size = font_size;
+        }
+    }
+}
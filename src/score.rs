@@ -0,0 +1,216 @@
+//! Score, accuracy and combo from the per-note judgements.
+//!
+//! The score is an approximation of Rizline's: the JS player has no scoring
+//! to follow, so `MAX_SCORE` and `GOOD_RATIO` are assumptions, not values
+//! taken from the game. Every note is worth the same, except holds which
+//! count twice (head and end) as `drawCombo` in js/format.js counts them. A
+//! Perfect earns the full value of a note and a Good `GOOD_RATIO` of it; the
+//! total over a full chart is `MAX_SCORE`.
+
+use serde::Serialize;
+
+use crate::chart::{Chart, NoteType};
+use crate::game::NoteState;
+use crate::judge::{Judge, Judgement, NoteResult};
+
+/// Score of a chart played all Perfect; assumed, like the usual million-point scale
+pub const MAX_SCORE: u32 = 1_000_000;
+
+/// Share of a note's value earned by a Good; assumed
+pub const GOOD_RATIO: f64 = 0.6;
+
+/// Score summary, serialized as the JSON result
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScoreSummary {
+    pub songs_name: String,
+    pub score: u32,
+    /// Percentage of the value of judged notes earned, 0 to 100
+    pub accuracy: f64,
+    /// Current combo, holds counting twice
+    pub combo: u32,
+    pub max_combo: u32,
+    pub perfect: u32,
+    pub good: u32,
+    pub miss: u32,
+    /// Notes judged so far
+    pub judged: u32,
+    /// Notes in the chart
+    pub notes: u32,
+}
+
+/// Notes of a chart in time order with their weights
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "player", derive(bevy::prelude::Resource))]
+pub struct ScoreModel {
+    songs_name: String,
+    /// `(line_index, note_index, weight)` sorted by note time
+    order: Vec<(usize, usize, u32)>,
+    total_weight: u32,
+}
+
+impl ScoreModel {
+    pub fn new(chart: &Chart) -> Self {
+        let mut notes: Vec<(f64, usize, usize, u32)> = chart
+            .lines
+            .iter()
+            .enumerate()
+            .flat_map(|(line_index, line)| {
+                line.notes
+                    .iter()
+                    .enumerate()
+                    .map(move |(note_index, note)| {
+                        let weight = if NoteType::from(note.note_type) == NoteType::Hold {
+                            2
+                        } else {
+                            1
+                        };
+                        (note.time, line_index, note_index, weight)
+                    })
+            })
+            .collect();
+        notes.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        Self {
+            songs_name: chart.songs_name.clone(),
+            total_weight: notes.iter().map(|note| note.3).sum(),
+            order: notes
+                .into_iter()
+                .map(|(_, line_index, note_index, weight)| (line_index, note_index, weight))
+                .collect(),
+        }
+    }
+
+    /// Summarize the judgements in `states`, indexed by line then note
+    pub fn summarize(&self, states: &[Vec<NoteState>]) -> ScoreSummary {
+        self.summarize_results(|line_index, note_index| {
+            states
+                .get(line_index)
+                .and_then(|line| line.get(note_index))
+                .and_then(|state| state.result)
+        })
+    }
+
+    /// Summarize the judgements made by `judge`
+    pub fn summarize_judge(&self, judge: &Judge) -> ScoreSummary {
+        self.summarize_results(|line_index, note_index| judge.result(line_index, note_index))
+    }
+
+    /// Summarize the result of each note by line and note index.
+    /// Notes without a result are not judged yet (or were skipped) and don't count.
+    fn summarize_results(
+        &self,
+        result: impl Fn(usize, usize) -> Option<NoteResult>,
+    ) -> ScoreSummary {
+        let mut summary = ScoreSummary {
+            songs_name: self.songs_name.clone(),
+            notes: self.order.len() as u32,
+            ..Default::default()
+        };
+        let mut earned = 0.0;
+        let mut judged_weight = 0;
+
+        for &(line_index, note_index, weight) in &self.order {
+            let Some(result) = result(line_index, note_index) else {
+                continue;
+            };
+
+            summary.judged += 1;
+            judged_weight += weight;
+            match result.judgement {
+                Judgement::Perfect => {
+                    summary.perfect += 1;
+                    earned += weight as f64;
+                }
+                Judgement::Good => {
+                    summary.good += 1;
+                    earned += weight as f64 * GOOD_RATIO;
+                }
+                Judgement::Miss => summary.miss += 1,
+            }
+
+            if result.judgement == Judgement::Miss {
+                summary.combo = 0;
+            } else {
+                summary.combo += weight;
+                summary.max_combo = summary.max_combo.max(summary.combo);
+            }
+        }
+
+        if self.total_weight > 0 {
+            summary.score = (MAX_SCORE as f64 * earned / self.total_weight as f64).round() as u32;
+        }
+        if judged_weight > 0 {
+            summary.accuracy = 100.0 * earned / judged_weight as f64;
+        }
+        summary
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::chart::tests::test_chart;
+    use crate::judge::{InputAction, InputEvent, InputSource, JudgeWindows};
+
+    fn tap(time: f64) -> Value {
+        json!({ "type": 0, "time": time, "floorPosition": 0.0, "otherInformations": [] })
+    }
+
+    fn hold(time: f64, end: f64) -> Value {
+        json!({ "type": 2, "time": time, "floorPosition": 0.0, "otherInformations": [end] })
+    }
+
+    /// Summary of pressing a key over each of `presses`, in seconds, on a chart of `notes`
+    fn play(notes: Value, presses: &[(f64, f64)]) -> ScoreSummary {
+        let chart = test_chart(notes);
+        let mut judge = Judge::new(&chart, JudgeWindows::default(), 0.0);
+        for &(down, up) in presses {
+            for (time, action) in [(down, InputAction::Down), (up, InputAction::Up)] {
+                judge.input(&InputEvent {
+                    time,
+                    action,
+                    source: InputSource::Key("A".into()),
+                    x: None,
+                });
+            }
+        }
+        judge.advance(f64::INFINITY);
+        ScoreModel::new(&chart).summarize_judge(&judge)
+    }
+
+    #[test]
+    fn all_perfect_scores_the_maximum() {
+        let notes = json!([tap(1.0), tap(2.0), hold(3.0, 4.0)]);
+        let summary = play(notes, &[(1.0, 1.1), (2.0, 2.1), (3.0, 4.0)]);
+        assert_eq!(summary.score, MAX_SCORE);
+        assert_eq!(summary.accuracy, 100.0);
+        assert_eq!(summary.perfect, 3);
+        assert_eq!((summary.combo, summary.max_combo), (4, 4));
+    }
+
+    #[test]
+    fn goods_and_misses_earn_part_of_the_score() {
+        // Perfect, Good, Miss and a Perfect hold worth two notes
+        let notes = json!([tap(1.0), tap(2.0), tap(3.0), hold(4.0, 5.0)]);
+        let summary = play(notes, &[(1.0, 1.1), (2.06, 2.1), (4.0, 5.0)]);
+        assert_eq!((summary.perfect, summary.good, summary.miss), (2, 1, 1));
+        let earned = 1.0 + GOOD_RATIO + 2.0;
+        assert_eq!(
+            summary.score,
+            (MAX_SCORE as f64 * earned / 5.0).round() as u32
+        );
+        assert!((summary.accuracy - 100.0 * earned / 5.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn miss_resets_the_combo() {
+        let notes = json!([tap(1.0), tap(2.0), tap(3.0), tap(4.0)]);
+        let summary = play(notes, &[(1.0, 1.1), (2.0, 2.1), (4.0, 4.1)]);
+        assert_eq!(summary.miss, 1);
+        assert_eq!(summary.combo, 1);
+        assert_eq!(summary.max_combo, 2);
+    }
+}
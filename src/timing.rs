@@ -1,66 +1,95 @@
-use crate::chart::{BpmShift, KeyPoint};
+use crate::chart::{BpmShift, Chart, KeyPoint};
 use crate::easing::apply_ease;
 
-/// Convert seconds to tick based on BPM shifts
-pub fn seconds_to_tick(seconds: f64, bpm_shifts: &[BpmShift], base_bpm: f64) -> f64 {
-    if bpm_shifts.is_empty() {
-        return seconds / (60.0 / base_bpm);
-    }
-
-    let first = &bpm_shifts[0];
-    if seconds <= first.floor_position {
-        return seconds / (60.0 / (base_bpm * first.value));
-    }
+/// Pieces an eased tempo change between two BPM shifts is integrated in
+const EASED_BPM_STEPS: usize = 256;
 
-    for i in 1..bpm_shifts.len() {
-        let curr = &bpm_shifts[i];
-        let prev = &bpm_shifts[i - 1];
+/// Tick/seconds conversion of a chart, built once from its BPM and BPM shifts.
+///
+/// Times are derived from the BPM curve, not from the shifts' stored
+/// `floorPosition`, so stale values in hand-edited charts do no harm. A shift
+/// with ease type 0 keeps its tempo until the next shift; any other ease type
+/// ramps the tempo to the next shift's value along that curve. The curve is
+/// integrated as pieces of constant tempo, in which ticks and seconds are
+/// linear in each other; before the first and after the last shift the tempo
+/// of that shift applies.
+#[derive(Debug, Clone)]
+pub struct TimingMap {
+    /// Tick at which each piece starts
+    ticks: Vec<f64>,
+    /// Seconds at which each piece starts
+    seconds: Vec<f64>,
+    /// Seconds per tick within each piece
+    rates: Vec<f64>,
+}
 
-        if seconds <= curr.floor_position {
-            let tick_start = prev.time;
-            let tick_end = curr.time;
-            let time_start = prev.floor_position;
-            let time_end = curr.floor_position;
+impl TimingMap {
+    pub fn new(bpm_shifts: &[BpmShift], base_bpm: f64) -> Self {
+        let rate = |value: f64| 60.0 / (base_bpm * value);
+        let mut map = Self {
+            ticks: Vec::new(),
+            seconds: Vec::new(),
+            rates: Vec::new(),
+        };
 
-            let ratio = (seconds - time_start) / (time_end - time_start);
-            return tick_start + ratio * (tick_end - tick_start);
+        let Some(first) = bpm_shifts.first() else {
+            map.push(0.0, 0.0, rate(1.0));
+            return map;
+        };
+        // Seconds count from tick 0 at the first shift's tempo
+        let mut seconds = first.time * rate(first.value);
+        for (i, shift) in bpm_shifts.iter().enumerate() {
+            let Some(next) = bpm_shifts.get(i + 1) else {
+                map.push(shift.time, seconds, rate(shift.value));
+                break;
+            };
+            let span = next.time - shift.time;
+            if shift.ease_type == 0 {
+                map.push(shift.time, seconds, rate(shift.value));
+                seconds += span * rate(shift.value);
+                continue;
+            }
+            // Each piece takes the eased tempo at its midpoint
+            for step in 0..EASED_BPM_STEPS {
+                let start = step as f64 / EASED_BPM_STEPS as f64;
+                let middle = (step as f64 + 0.5) / EASED_BPM_STEPS as f64;
+                let ease = apply_ease(shift.ease_type, middle as f32) as f64;
+                let piece_rate = rate(shift.value + (next.value - shift.value) * ease);
+                map.push(shift.time + start * span, seconds, piece_rate);
+                seconds += span / EASED_BPM_STEPS as f64 * piece_rate;
+            }
         }
+        map
     }
 
-    // Beyond last shift
-    let last = &bpm_shifts[bpm_shifts.len() - 1];
-    let extra_seconds = seconds - last.floor_position;
-    let extra_ticks = extra_seconds / (60.0 / (base_bpm * last.value));
-    last.time + extra_ticks
-}
-
-/// Convert tick to seconds based on BPM shifts
-pub fn tick_to_seconds(tick: f64, bpm_shifts: &[BpmShift], base_bpm: f64) -> f64 {
-    if bpm_shifts.is_empty() {
-        return tick * (60.0 / base_bpm);
+    pub fn from_chart(chart: &Chart) -> Self {
+        Self::new(&chart.bpm_shifts, chart.bpm)
     }
 
-    let first = &bpm_shifts[0];
-    if tick <= first.time {
-        return tick * (60.0 / (base_bpm * first.value));
+    fn push(&mut self, tick: f64, seconds: f64, rate: f64) {
+        self.ticks.push(tick);
+        self.seconds.push(seconds);
+        self.rates.push(rate);
     }
 
-    for i in 1..bpm_shifts.len() {
-        let curr = &bpm_shifts[i];
-        if tick <= curr.time {
-            let prev = &bpm_shifts[i - 1];
-            let ratio = (tick - prev.time) / (curr.time - prev.time);
-            let sec_start = prev.floor_position;
-            let sec_end = curr.floor_position;
-            return sec_start + ratio * (sec_end - sec_start);
-        }
+    /// Convert tick to seconds
+    pub fn tick_to_seconds(&self, tick: f64) -> f64 {
+        // Last piece starting at or before `tick`, or the first piece
+        let piece = self
+            .ticks
+            .partition_point(|&time| time <= tick)
+            .saturating_sub(1);
+        self.seconds[piece] + (tick - self.ticks[piece]) * self.rates[piece]
     }
 
-    // Beyond last shift
-    let last = &bpm_shifts[bpm_shifts.len() - 1];
-    let extra_ticks = tick - last.time;
-    let extra_seconds = extra_ticks * (60.0 / (base_bpm * last.value));
-    last.floor_position + extra_seconds
+    /// Convert seconds to tick
+    pub fn seconds_to_tick(&self, seconds: f64) -> f64 {
+        let piece = self
+            .seconds
+            .partition_point(|&time| time <= seconds)
+            .saturating_sub(1);
+        self.ticks[piece] + (seconds - self.seconds[piece]) / self.rates[piece]
+    }
 }
 
 /// Find interpolated value from keypoint events at given tick
@@ -108,63 +137,168 @@ pub fn find_value(tick: f64, events: &[KeyPoint]) -> f64 {
 
     if let (Some(e1), Some(e2)) = (event1, event2) {
         let t = (tick - e1.time) / (e2.time - e1.time);
-        let ease_value = apply_ease(e1.ease_type, t as f32) as f64;
+        let ease_value = e1.ease(t as f32) as f64;
         e1.value + (e2.value - e1.value) * ease_value
     } else {
         0.0
     }
 }
 
-/// Recalculate floor positions for speed keypoints
-pub fn recalculate_fps(speed_key_points: &mut [KeyPoint], bpm_shifts: &[BpmShift], base_bpm: f64) {
-    if speed_key_points.is_empty() {
-        return;
-    }
+/// Pieces an eased speed change between two keypoints is integrated in
+const EASED_SPEED_STEPS: usize = 256;
 
-    speed_key_points[0].fp = Some(0.0);
+/// Floor position of a canvas over time: the integral, in seconds, of its
+/// speed keypoints. It is 0 at the first keypoint and counts back from there
+/// at that keypoint's speed before it, so earlier line points keep their
+/// spacing.
+///
+/// As in js/format.js, a keypoint with ease type 0 keeps its speed until the
+/// next keypoint; any other ease type, or a custom ease, eases the speed to
+/// the next keypoint's value along that curve, over ticks. The curve is
+/// integrated once into pieces of constant speed, so evaluating it is a
+/// binary search.
+#[derive(Debug, Clone, Default)]
+pub struct FloorPositionMap {
+    /// Seconds at which each piece starts
+    seconds: Vec<f64>,
+    /// Floor position at which each piece starts
+    fps: Vec<f64>,
+    /// Speed within each piece
+    speeds: Vec<f64>,
+}
 
-    for i in 1..speed_key_points.len() {
-        let prev_time = speed_key_points[i - 1].time;
-        let prev_value = speed_key_points[i - 1].value;
-        let prev_fp = speed_key_points[i - 1].fp.unwrap_or(0.0);
+impl FloorPositionMap {
+    pub fn new(speed_key_points: &[KeyPoint], timing: &TimingMap) -> Self {
+        let mut map = Self::default();
+        let mut fp = 0.0;
 
-        let curr_time = speed_key_points[i].time;
-        let time_diff =
-            tick_to_seconds(curr_time, bpm_shifts, base_bpm) - tick_to_seconds(prev_time, bpm_shifts, base_bpm);
+        for (i, point) in speed_key_points.iter().enumerate() {
+            let start = timing.tick_to_seconds(point.time);
+            let Some(next) = speed_key_points.get(i + 1) else {
+                map.push(start, fp, point.value);
+                break;
+            };
+            if point.ease_type == 0 && point.custom_ease.is_none() {
+                map.push(start, fp, point.value);
+                fp += point.value * (timing.tick_to_seconds(next.time) - start);
+                continue;
+            }
+            // Each piece takes the eased speed at its midpoint
+            let span = next.time - point.time;
+            let mut piece_start = start;
+            for step in 0..EASED_SPEED_STEPS {
+                let middle = (step as f64 + 0.5) / EASED_SPEED_STEPS as f64;
+                let ease = point.ease(middle as f32) as f64;
+                let speed = point.value + (next.value - point.value) * ease;
+                let piece_end = timing.tick_to_seconds(
+                    point.time + (step + 1) as f64 / EASED_SPEED_STEPS as f64 * span,
+                );
+                map.push(piece_start, fp, speed);
+                fp += speed * (piece_end - piece_start);
+                piece_start = piece_end;
+            }
+        }
+        map
+    }
 
-        speed_key_points[i].fp = Some(prev_fp + prev_value * time_diff);
+    fn push(&mut self, seconds: f64, fp: f64, speed: f64) {
+        self.seconds.push(seconds);
+        self.fps.push(fp);
+        self.speeds.push(speed);
     }
-}
 
-/// Calculate floor position from speed keypoints at given time (in seconds)
-pub fn speed_to_fp(timer: f64, speed_key_points: &[KeyPoint], bpm_shifts: &[BpmShift], base_bpm: f64) -> f64 {
-    if speed_key_points.is_empty() {
-        return 0.0;
+    /// Floor position at `seconds`, negative before the first keypoint
+    pub fn fp_at(&self, seconds: f64) -> f64 {
+        if self.seconds.is_empty() {
+            return 0.0;
+        }
+        let piece = self
+            .seconds
+            .partition_point(|&time| time <= seconds)
+            .saturating_sub(1);
+        self.fps[piece] + (seconds - self.seconds[piece]) * self.speeds[piece]
     }
+}
 
-    // Binary search for the keypoint
-    let mut left = 0;
-    let mut right = speed_key_points.len() - 1;
-    let mut target_index = right;
+#[cfg(test)]
+mod tests {
+    use super::TimingMap;
+    use crate::chart::BpmShift;
 
-    while left <= right {
-        let mid = (left + right) / 2;
-        let mid_time = tick_to_seconds(speed_key_points[mid].time, bpm_shifts, base_bpm);
+    /// xorshift64, enough to sweep shift sets reproducibly without a dependency
+    struct Rng(u64);
 
-        if mid_time <= timer {
-            target_index = mid;
-            left = mid + 1;
-        } else {
-            if mid == 0 {
-                break;
-            }
-            right = mid - 1;
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
         }
+
+        /// Uniform in `low..high`
+        fn range(&mut self, low: f64, high: f64) -> f64 {
+            low + (high - low) * (self.next() >> 11) as f64 / (1u64 << 53) as f64
+        }
+    }
+
+    /// Sorted shifts with eased and constant tempos, some of them zero-length
+    fn random_shifts(rng: &mut Rng) -> Vec<BpmShift> {
+        let count = rng.next() % 12;
+        let mut time = rng.range(-8.0, 8.0);
+        (0..count)
+            .map(|_| {
+                // One shift in four starts where the previous one does
+                if !rng.next().is_multiple_of(4) {
+                    time += rng.range(0.0, 64.0);
+                }
+                BpmShift {
+                    time,
+                    value: rng.range(0.1, 4.0),
+                    ease_type: if rng.next().is_multiple_of(2) {
+                        0
+                    } else {
+                        (rng.next() % 19) as u8
+                    },
+                    floor_position: 0.0,
+                    extra: Default::default(),
+                }
+            })
+            .collect()
     }
 
-    let current = &speed_key_points[target_index];
-    let current_time = tick_to_seconds(current.time, bpm_shifts, base_bpm);
-    let t2 = timer - current_time;
+    #[test]
+    fn seconds_and_ticks_invert_each_other() {
+        let mut rng = Rng(0x9e37_79b9_7f4a_7c15);
+        for _ in 0..500 {
+            let shifts = random_shifts(&mut rng);
+            let bpm = rng.range(30.0, 300.0);
+            let timing = TimingMap::new(&shifts, bpm);
+            let end = shifts.last().map_or(0.0, |shift| shift.time);
+            let mut ticks: Vec<f64> = (0..64).map(|_| rng.range(-16.0, end + 16.0)).collect();
+            ticks.extend(shifts.iter().map(|shift| shift.time));
+
+            for tick in ticks {
+                let seconds = timing.tick_to_seconds(tick);
+                let back = timing.seconds_to_tick(seconds);
+                assert!(
+                    (back - tick).abs() < 1e-6,
+                    "tick {} -> {}s -> tick {} in {:?}",
+                    tick,
+                    seconds,
+                    back,
+                    shifts
+                );
 
-    current.fp.unwrap_or(0.0) + t2 * current.value
+                let back = timing.tick_to_seconds(timing.seconds_to_tick(seconds));
+                assert!(
+                    (back - seconds).abs() < 1e-6,
+                    "{}s -> tick -> {}s in {:?}",
+                    seconds,
+                    back,
+                    shifts
+                );
+            }
+        }
+    }
 }
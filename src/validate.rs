@@ -0,0 +1,527 @@
+//! Chart validation, the Rust counterpart of js/formatCheck.js.
+//!
+//! Errors are what the player cannot work around, such as a BPM that is not
+//! positive, keypoints out of time order or notes on a line without points,
+//! and make `Chart::load` reject the chart. Warnings are what the renderer
+//! silently works around: unknown ease types, which ease linearly as in
+//! js/formatCheck.js, canvas indices it skips, holds without an end and so on.
+//! Every diagnostic points into the chart with a JSON path such as
+//! `lines[3].linePoints[12].easeType`.
+
+use std::fmt;
+
+use serde::Serialize;
+
+use crate::chart::{Chart, KeyPoint, NoteType};
+use crate::easing::{CustomEase, Ease};
+use crate::timing::TimingMap;
+
+/// Seconds a BPM shift's stored `floorPosition` may be off before it is reported
+const FLOOR_POSITION_TOLERANCE: f64 = 1e-3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Severity {
+    Info,
+    Warn,
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Severity::Info => "info",
+            Severity::Warn => "warn",
+            Severity::Error => "error",
+        })
+    }
+}
+
+/// Problem found in a chart
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// JSON path of the offending value
+    pub path: String,
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}: {}", self.severity, self.path, self.message)
+    }
+}
+
+/// Check a parsed chart, in file order
+pub fn validate(chart: &Chart) -> Vec<Diagnostic> {
+    let mut checker = Checker::default();
+    checker.check_chart(chart);
+    checker.diagnostics
+}
+
+#[derive(Default)]
+struct Checker {
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Checker {
+    fn push(&mut self, severity: Severity, path: impl Into<String>, message: impl Into<String>) {
+        self.diagnostics.push(Diagnostic {
+            severity,
+            path: path.into(),
+            message: message.into(),
+        });
+    }
+
+    fn check_chart(&mut self, chart: &Chart) {
+        if chart.file_version == 1 {
+            self.push(
+                Severity::Info,
+                "fileVersion",
+                "fileVersion 1 is still being adapted",
+            );
+        }
+
+        if chart.themes.is_empty() {
+            self.push(
+                Severity::Warn,
+                "themes",
+                "no themes; every color falls back to transparent black",
+            );
+        }
+        for (i, theme) in chart.themes.iter().enumerate() {
+            if theme.colors_list.len() < 3 {
+                self.push(
+                    Severity::Warn,
+                    format!("themes[{}].colorsList", i),
+                    format!(
+                        "{} colors, expected background, note and effect colors",
+                        theme.colors_list.len()
+                    ),
+                );
+            }
+        }
+
+        for i in 0..chart.challenge_times.len() {
+            // Challenge `i` shows theme `i + 1`
+            if i + 1 >= chart.themes.len() {
+                self.push(
+                    Severity::Warn,
+                    format!("challengeTimes[{}]", i),
+                    format!("no theme {} to show during this challenge", i + 1),
+                );
+            }
+        }
+        self.check_sorted(
+            Severity::Warn,
+            "challengeTimes",
+            "start",
+            chart.challenge_times.iter().map(|c| c.start),
+        );
+
+        if chart.bpm <= 0.0 {
+            self.push(
+                Severity::Error,
+                "bPM",
+                format!("BPM {} is not positive", chart.bpm),
+            );
+        }
+        if chart.bpm_shifts.is_empty() {
+            self.push(
+                Severity::Warn,
+                "bpmShifts",
+                "missing or empty; the base BPM is used throughout",
+            );
+        }
+        for (i, shift) in chart.bpm_shifts.iter().enumerate() {
+            self.check_ease(&format!("bpmShifts[{}]", i), shift.ease_type);
+            if shift.value <= 0.0 {
+                self.push(
+                    Severity::Error,
+                    format!("bpmShifts[{}].value", i),
+                    format!("BPM multiplier {} is not positive", shift.value),
+                );
+            }
+        }
+        let bpm_sorted = self.check_sorted(
+            Severity::Error,
+            "bpmShifts",
+            "time",
+            chart.bpm_shifts.iter().map(|s| s.time),
+        );
+        if bpm_sorted && chart.bpm > 0.0 && chart.bpm_shifts.iter().all(|shift| shift.value > 0.0) {
+            self.check_bpm_floor_positions(chart);
+        }
+
+        if chart.lines.is_empty() {
+            self.push(
+                Severity::Warn,
+                "lines",
+                "missing or empty; there is nothing to play",
+            );
+        }
+        for (i, line) in chart.lines.iter().enumerate() {
+            let path = format!("lines[{}]", i);
+            if line.line_points.is_empty() && !line.notes.is_empty() {
+                self.push(
+                    Severity::Error,
+                    format!("{}.linePoints", path),
+                    format!(
+                        "empty, but the line has {} notes to place on it",
+                        line.notes.len()
+                    ),
+                );
+            }
+            for (j, point) in line.line_points.iter().enumerate() {
+                let point_path = format!("{}.linePoints[{}]", path, j);
+                self.check_ease(&point_path, point.ease_type);
+                if let Some(custom_ease) = &point.custom_ease {
+                    self.check_custom_ease(&point_path, custom_ease);
+                }
+                self.check_canvas_index(
+                    chart,
+                    &format!("{}.canvasIndex", point_path),
+                    point.canvas_index,
+                );
+            }
+            self.check_sorted(
+                Severity::Error,
+                &format!("{}.linePoints", path),
+                "time",
+                line.line_points.iter().map(|p| p.time),
+            );
+
+            for (j, note) in line.notes.iter().enumerate() {
+                let note_path = format!("{}.notes[{}]", path, j);
+                if note.note_type > NoteType::Hold as u8 {
+                    self.push(
+                        Severity::Warn,
+                        format!("{}.type", note_path),
+                        format!("unknown note type {}, treated as a tap", note.note_type),
+                    );
+                }
+                if NoteType::from(note.note_type) != NoteType::Hold {
+                    continue;
+                }
+                match note.other_informations.first() {
+                    None => self.push(
+                        Severity::Warn,
+                        format!("{}.otherInformations", note_path),
+                        "hold has no end time",
+                    ),
+                    Some(&end) if end < note.time => self.push(
+                        Severity::Warn,
+                        format!("{}.otherInformations[0]", note_path),
+                        format!("hold ends at {} before it starts at {}", end, note.time),
+                    ),
+                    Some(_) => {}
+                }
+                if let Some(&canvas_index) = note.other_informations.get(1) {
+                    self.check_canvas_index(
+                        chart,
+                        &format!("{}.otherInformations[1]", note_path),
+                        canvas_index as usize,
+                    );
+                }
+            }
+
+            self.check_sorted(
+                Severity::Warn,
+                &format!("{}.judgeRingColor", path),
+                "time",
+                line.judge_ring_color.iter().map(|c| c.time),
+            );
+            self.check_sorted(
+                Severity::Warn,
+                &format!("{}.lineColor", path),
+                "time",
+                line.line_color.iter().map(|c| c.time),
+            );
+        }
+
+        if chart.canvas_moves.is_empty() {
+            self.push(
+                Severity::Error,
+                "canvasMoves",
+                "missing or empty; lines have nowhere to be drawn",
+            );
+        }
+        for (i, canvas) in chart.canvas_moves.iter().enumerate() {
+            let path = format!("canvasMoves[{}]", i);
+            if canvas.index != i {
+                self.push(
+                    Severity::Warn,
+                    format!("{}.index", path),
+                    format!("index {} differs from the position {}, which is what canvasIndex refers to", canvas.index, i),
+                );
+            }
+            self.check_key_points(
+                &format!("{}.xPositionKeyPoints", path),
+                &canvas.x_position_key_points,
+            );
+            self.check_key_points(
+                &format!("{}.speedKeyPoints", path),
+                &canvas.speed_key_points,
+            );
+        }
+
+        let camera = &chart.camera_move;
+        if camera.scale_key_points.is_empty() && camera.x_position_key_points.is_empty() {
+            self.push(
+                Severity::Error,
+                "cameraMove",
+                "missing or without keypoints; a camera scale of 0 hides everything",
+            );
+        } else {
+            if camera.scale_key_points.is_empty() {
+                self.push(
+                    Severity::Error,
+                    "cameraMove.scaleKeyPoints",
+                    "no keypoints; a camera scale of 0 hides everything",
+                );
+            } else {
+                self.check_key_points("cameraMove.scaleKeyPoints", &camera.scale_key_points);
+            }
+            self.check_key_points(
+                "cameraMove.xPositionKeyPoints",
+                &camera.x_position_key_points,
+            );
+        }
+    }
+
+    fn check_key_points(&mut self, path: &str, points: &[KeyPoint]) {
+        if points.is_empty() {
+            self.push(Severity::Warn, path, "no keypoints; the value stays 0");
+        }
+        for (i, point) in points.iter().enumerate() {
+            let point_path = format!("{}[{}]", path, i);
+            self.check_ease(&point_path, point.ease_type);
+            if let Some(custom_ease) = &point.custom_ease {
+                self.check_custom_ease(&point_path, custom_ease);
+            }
+        }
+        self.check_sorted(Severity::Error, path, "time", points.iter().map(|p| p.time));
+    }
+
+    /// `path` is the object holding the `easeType`
+    fn check_ease(&mut self, path: &str, ease_type: u8) {
+        if let Err(e) = Ease::try_from(ease_type) {
+            self.push(
+                Severity::Warn,
+                format!("{}.easeType", path),
+                format!("{}; eased linearly, as formatCheck.js sets it to 0", e),
+            );
+        }
+    }
+
+    /// `path` is the object holding the `customEase`
+    fn check_custom_ease(&mut self, path: &str, custom_ease: &CustomEase) {
+        let path = format!("{}.customEase", path);
+        match *custom_ease {
+            CustomEase::Bezier {
+                points: [x1, _, x2, _],
+            } => {
+                if !(0.0..=1.0).contains(&x1) || !(0.0..=1.0).contains(&x2) {
+                    self.push(
+                        Severity::Warn,
+                        format!("{}.points", path),
+                        format!("bezier x coordinates {} and {} must lie in 0..=1", x1, x2),
+                    );
+                }
+            }
+            CustomEase::Range {
+                ease_type,
+                left,
+                right,
+            } => {
+                if let Err(e) = Ease::try_from(ease_type) {
+                    self.push(
+                        Severity::Warn,
+                        format!("{}.easeType", path),
+                        format!("{}; eased linearly, as formatCheck.js sets it to 0", e),
+                    );
+                }
+                if !(0.0 <= left && left < right && right <= 1.0) {
+                    self.push(
+                        Severity::Warn,
+                        path,
+                        format!(
+                            "range {}..{} is not an increasing part of 0..=1",
+                            left, right
+                        ),
+                    );
+                }
+            }
+            // Unknown names are rejected when the chart is read
+            CustomEase::Named { .. } => {}
+        }
+    }
+
+    fn check_canvas_index(&mut self, chart: &Chart, path: &str, canvas_index: usize) {
+        if canvas_index >= chart.canvas_moves.len() {
+            self.push(
+                Severity::Warn,
+                path,
+                format!(
+                    "canvas {} does not exist, there are {} canvases",
+                    canvas_index,
+                    chart.canvas_moves.len()
+                ),
+            );
+        }
+    }
+
+    /// Stored shift times that disagree with the BPM curve, which is what the player uses
+    fn check_bpm_floor_positions(&mut self, chart: &Chart) {
+        let timing = TimingMap::from_chart(chart);
+        for (i, shift) in chart.bpm_shifts.iter().enumerate() {
+            let seconds = timing.tick_to_seconds(shift.time);
+            if (shift.floor_position - seconds).abs() > FLOOR_POSITION_TOLERANCE {
+                self.push(
+                    Severity::Info,
+                    format!("bpmShifts[{}].floorPosition", i),
+                    format!(
+                        "stored {} but the BPM curve reaches this shift at {:.6}s, which is used instead",
+                        shift.floor_position, seconds
+                    ),
+                );
+            }
+        }
+    }
+
+    /// Report the first element of the array at `path` whose time (in `field`) goes backward;
+    /// false if there is one
+    fn check_sorted(
+        &mut self,
+        severity: Severity,
+        path: &str,
+        field: &str,
+        times: impl Iterator<Item = f64>,
+    ) -> bool {
+        let mut previous = f64::NEG_INFINITY;
+        for (i, time) in times.enumerate() {
+            if time < previous {
+                self.push(
+                    severity,
+                    format!("{}[{}].{}", path, i, field),
+                    format!("time {} is before the previous time {}", time, previous),
+                );
+                return false;
+            }
+            previous = time;
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::chart::tests::test_chart_json;
+
+    fn check(file: Value) -> Vec<Diagnostic> {
+        validate(&Chart::from_json(&file.to_string()).unwrap())
+    }
+
+    /// Severity of the diagnostic at `path`, which must be the only one
+    fn only(diagnostics: &[Diagnostic], path: &str) -> Severity {
+        assert_eq!(diagnostics.len(), 1, "{:?}", diagnostics);
+        assert_eq!(diagnostics[0].path, path);
+        diagnostics[0].severity
+    }
+
+    fn tap(time: f64) -> Value {
+        json!({ "type": 0, "time": time, "floorPosition": 0.0, "otherInformations": [] })
+    }
+
+    #[test]
+    fn valid_chart_has_no_diagnostics() {
+        assert_eq!(check(test_chart_json(json!([tap(1.0)]))), Vec::new());
+    }
+
+    #[test]
+    fn unknown_ease_type_warns_at_its_path() {
+        let mut file = test_chart_json(json!([]));
+        file["lines"][0]["linePoints"][1]["easeType"] = json!(99);
+        let diagnostics = check(file);
+        assert_eq!(
+            only(&diagnostics, "lines[0].linePoints[1].easeType"),
+            Severity::Warn
+        );
+        assert_eq!(
+            diagnostics[0].to_string(),
+            "warn: lines[0].linePoints[1].easeType: unknown ease type 99, known types are 0-18; \
+             eased linearly, as formatCheck.js sets it to 0"
+        );
+    }
+
+    #[test]
+    fn canvas_index_out_of_range_warns() {
+        let mut file = test_chart_json(json!([]));
+        file["lines"][0]["linePoints"][0]["canvasIndex"] = json!(3);
+        let diagnostics = check(file);
+        assert_eq!(
+            only(&diagnostics, "lines[0].linePoints[0].canvasIndex"),
+            Severity::Warn
+        );
+    }
+
+    #[test]
+    fn unsorted_keypoints_are_errors() {
+        let mut file = test_chart_json(json!([]));
+        let points = &mut file["canvasMoves"][0]["speedKeyPoints"];
+        let mut later = points[0].clone();
+        later["time"] = json!(8.0);
+        let mut earlier = points[0].clone();
+        earlier["time"] = json!(4.0);
+        *points = json!([points[0], later, earlier]);
+        let diagnostics = check(file);
+        assert_eq!(
+            only(&diagnostics, "canvasMoves[0].speedKeyPoints[2].time"),
+            Severity::Error
+        );
+    }
+
+    #[test]
+    fn hold_ending_before_it_starts_warns() {
+        let hold =
+            json!({ "type": 2, "time": 4.0, "floorPosition": 0.0, "otherInformations": [3.0] });
+        let diagnostics = check(test_chart_json(json!([hold])));
+        assert_eq!(
+            only(&diagnostics, "lines[0].notes[0].otherInformations[0]"),
+            Severity::Warn
+        );
+    }
+
+    #[test]
+    fn empty_canvas_moves_are_an_error() {
+        let mut file = test_chart_json(json!([]));
+        file["canvasMoves"] = json!([]);
+        let diagnostics = check(file);
+        let empty = diagnostics
+            .iter()
+            .find(|d| d.path == "canvasMoves")
+            .unwrap();
+        assert_eq!(empty.severity, Severity::Error);
+        // The line points now name a canvas that does not exist
+        assert!(diagnostics
+            .iter()
+            .filter(|d| d.path != "canvasMoves")
+            .all(|d| d.severity == Severity::Warn && d.path.ends_with(".canvasIndex")));
+    }
+
+    #[test]
+    fn notes_on_a_line_without_points_are_an_error() {
+        let mut file = test_chart_json(json!([tap(1.0)]));
+        file["lines"][0]["linePoints"] = json!([]);
+        assert_eq!(
+            only(&check(file.clone()), "lines[0].linePoints"),
+            Severity::Error
+        );
+
+        file["lines"][0]["notes"] = json!([]);
+        assert_eq!(check(file), Vec::new());
+    }
+}